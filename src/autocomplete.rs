@@ -67,12 +67,11 @@ impl PartialOrd for CompletionChoice {
 
 pub mod autocomplete {
     use crate::autocomplete::CompletionChoice;
-    use crate::userenv::userenv;
+    use crate::executables::executables;
+    use crate::lexer::lexer;
     use anyhow::Result;
-    use std::ffi::OsString;
-    use std::os::unix::fs::PermissionsExt;
     use std::path::{Path, PathBuf};
-    use std::{env, error, fmt, fs, io};
+    use std::{env, fs, io};
 
     enum CompletionType {
         Executable,
@@ -84,44 +83,22 @@ pub mod autocomplete {
     struct CommandArguments {
         command: String,
         arguments: Vec<String>,
-    }
-
-    impl fmt::Display for CommandArguments {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(f, "{} {}", self.command, self.arguments.join(" "))
-        }
-    }
-
-    #[derive(Debug)]
-    enum AutoCompleteError {
-        NonUnicodePath(OsString),
-    }
-
-    impl fmt::Display for AutoCompleteError {
-        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            match *self {
-                AutoCompleteError::NonUnicodePath(_) => write!(f, "Invalid Unicode path"),
-            }
-        }
-    }
-    impl error::Error for AutoCompleteError {
-        fn description(&self) -> &str {
-            match *self {
-                AutoCompleteError::NonUnicodePath(_) => "Invalid Unicode path",
-            }
-        }
+        // Command line as typed before the word being completed, kept as is.
+        typed_prefix: String,
+        // Word being completed, as typed.
+        typed_word: String,
     }
 
     pub fn autocomplete(command: &str) -> Result<Vec<CompletionChoice>> {
         let command_args = build_command_arguments(command);
         let mut choices: Vec<CompletionChoice> = match get_completion_type(&command_args) {
             CompletionType::File => autocomplete_path(command_args, None)?,
-            CompletionType::Executable => userenv::path()
-                .split(':')
-                .filter_map(|path| {
-                    executables_in_path_with_prefix(path, &command_args.command).ok()
+            CompletionType::Executable => executables::with_prefix(&command_args.command)
+                .into_iter()
+                .map(|name| CompletionChoice {
+                    completion: format!("{}{}", command_args.typed_prefix, lexer::quote(&name)),
+                    label: name,
                 })
-                .flatten()
                 .collect::<Vec<CompletionChoice>>(),
         };
         choices.sort();
@@ -138,48 +115,27 @@ pub mod autocomplete {
     }
 
     fn build_command_arguments(input_command: &str) -> CommandArguments {
-        let items: Vec<&str> = input_command.split(' ').collect();
-        let mut arguments: Vec<String> = Vec::new();
-        let mut command: String = String::new();
-        for (i, item) in items.iter().enumerate() {
-            if i == 0 {
-                command = item.to_string();
-            } else {
-                arguments.push(item.to_string());
-            }
+        // The command line is being typed, so an unclosed quote is fine.
+        let (tokens, _error) = lexer::tokenize_partial(input_command);
+        let (typed_prefix, typed_word) = match tokens.last() {
+            Some(token) if !lexer::ends_with_separator(input_command) => (
+                &input_command[..token.start],
+                &input_command[token.start..token.end],
+            ),
+            _ => (input_command, ""),
+        };
+        let mut items: Vec<String> = tokens.into_iter().map(|token| token.value).collect();
+        if lexer::ends_with_separator(input_command) {
+            items.push(String::new());
+        }
+        let mut items = items.into_iter();
+        let command = items.next().unwrap_or_default();
+        CommandArguments {
+            command,
+            arguments: items.collect(),
+            typed_prefix: typed_prefix.to_string(),
+            typed_word: typed_word.to_string(),
         }
-        CommandArguments { command, arguments }
-    }
-
-    fn executables_in_path_with_prefix(path: &str, prefix: &str) -> Result<Vec<CompletionChoice>> {
-        let paths = fs::read_dir(path)?
-            .map(|res| res.map(|e| e.path()))
-            .collect::<Result<Vec<_>, io::Error>>()?;
-        let paths = paths
-            .iter()
-            .filter_map(|p| p.file_name().map(|filename| (p, filename)))
-            .filter_map(|(p, name)| name.to_str().map(|name| (p, name)))
-            .collect::<Vec<(&PathBuf, &str)>>();
-        let completions = paths
-            .iter()
-            .filter(|(p, _name)| p.starts_with(prefix))
-            .filter_map(|(p, name)| match p.metadata() {
-                Ok(metadata) => Some((metadata, name)),
-                Err(err) => {
-                    log::error!("Cannot read metadata: {:?}", err);
-                    None
-                }
-            })
-            .filter(|(metadata, _name)| {
-                let permissions = metadata.permissions();
-                permissions.mode() & 0o111 != 0
-            })
-            .map(|(_p, name)| CompletionChoice {
-                label: name.to_string(),
-                completion: name.to_string(),
-            })
-            .collect::<Vec<CompletionChoice>>();
-        Ok(completions)
     }
 
     fn autocomplete_path(
@@ -221,19 +177,35 @@ pub mod autocomplete {
             .filter(|p| p.starts_with(current_arg))
             .map(|p| CompletionChoice {
                 label: p.to_string(),
-                completion: path_full_completion(command_args.clone(), p.to_string()),
+                completion: path_full_completion(&command_args, prefix.as_deref(), p),
             })
             .collect::<Vec<CompletionChoice>>();
         Ok(completions)
     }
 
-    fn path_full_completion(mut args: CommandArguments, completion: String) -> String {
-        if !args.arguments.is_empty() {
-            args.arguments.pop();
+    // Replaces the word being completed by the path. The directory of the
+    // word is kept as typed, with its variables and quotes, when it reads
+    // back as the directory of the path.
+    fn path_full_completion(args: &CommandArguments, prefix: Option<&str>, path: &str) -> String {
+        let typed_dir = match args.typed_word.rfind('/') {
+            Some(last_slash) => &args.typed_word[..=last_slash],
+            None => "",
+        };
+        let prefix = prefix.unwrap_or("");
+        let typed_dir_matches = match lexer::tokenize(typed_dir) {
+            Ok(tokens) if tokens.is_empty() => prefix.is_empty(),
+            Ok(tokens) => tokens.len() == 1 && tokens[0].value == prefix,
+            Err(_) => false,
+        };
+        match typed_dir_matches && path.starts_with(prefix) {
+            true => format!(
+                "{}{}{}",
+                args.typed_prefix,
+                typed_dir,
+                lexer::quote(&path[prefix.len()..])
+            ),
+            false => format!("{}{}", args.typed_prefix, lexer::quote(path)),
         }
-        args.arguments.push(completion);
-        log::debug!("Pushing completion: {:?}", args.to_string());
-        args.to_string()
     }
 
     fn directory_from_path(path: &str) -> Option<String> {
@@ -260,6 +232,8 @@ pub mod autocomplete {
     #[cfg(test)]
     mod tests {
         use super::*;
+        use crate::userenv::userenv;
+
         #[test]
         fn test_autocomplete_path() {
             //TODO: use random dir name
@@ -267,10 +241,7 @@ pub mod autocomplete {
             fs::write("/tmp/manette/test/a", "").unwrap();
             fs::write("/tmp/manette/test/b", "").unwrap();
             let test_path = PathBuf::from("/tmp/manette/test");
-            let test_args = CommandArguments {
-                command: String::from("ls"),
-                arguments: vec![],
-            };
+            let test_args = build_command_arguments("ls ");
             let mut results =
                 autocomplete_path(test_args.clone(), Some(test_path.clone())).unwrap();
             results.sort();
@@ -301,10 +272,7 @@ pub mod autocomplete {
             // list files in subdir
             fs::write("/tmp/manette/test/dir/a", "").unwrap();
             fs::write("/tmp/manette/test/dir/b", "").unwrap();
-            let test_args = CommandArguments {
-                command: String::from("ls"),
-                arguments: vec![String::from("dir/")],
-            };
+            let test_args = build_command_arguments("ls dir/");
             let mut results =
                 autocomplete_path(test_args.clone(), Some(test_path.clone())).unwrap();
             results.sort();
@@ -331,10 +299,7 @@ pub mod autocomplete {
             fs::create_dir_all(test_dir.clone()).unwrap();
             fs::write(format!("{}/a", test_dir), "").unwrap();
             fs::write(format!("{}/b", test_dir), "").unwrap();
-            let test_args = CommandArguments {
-                command: String::from("ls"),
-                arguments: vec![format!("{}/", test_dir)],
-            };
+            let test_args = build_command_arguments(&format!("ls {}/", test_dir));
             let mut results = autocomplete_path(test_args.clone(), None).unwrap();
             results.sort();
             let expected_results = vec![
@@ -355,19 +320,63 @@ pub mod autocomplete {
 
         #[test]
         fn test_autocomplete_path_root() {
-            let test_args = CommandArguments {
-                command: String::from("ls"),
-                arguments: vec![format!("/tm")],
-            };
+            let test_args = build_command_arguments("ls /tm");
             let mut results = autocomplete_path(test_args.clone(), None).unwrap();
             results.sort();
             let expected_results = vec![CompletionChoice {
-                label: format!("/tmp/"),
-                completion: format!("ls /tmp/"),
+                label: String::from("/tmp/"),
+                completion: String::from("ls /tmp/"),
             }];
             assert_eq!(results, expected_results,);
         }
 
+        #[test]
+        fn test_build_command_arguments() {
+            let command_args = build_command_arguments("cd My\\ Doc");
+            assert_eq!(command_args.command, "cd");
+            assert_eq!(command_args.arguments, vec![String::from("My Doc")]);
+
+            let command_args = build_command_arguments("cd \"My Doc");
+            assert_eq!(command_args.arguments, vec![String::from("My Doc")]);
+
+            let command_args = build_command_arguments("ls ");
+            assert_eq!(command_args.arguments, vec![String::new()]);
+
+            let command_args = build_command_arguments("ls");
+            assert!(command_args.arguments.is_empty());
+
+            let command_args = build_command_arguments("ls My");
+            let completion = path_full_completion(&command_args, None, "My Documents/");
+            assert_eq!(completion, "ls My\\ Documents/");
+        }
+
+        #[test]
+        fn test_path_full_completion() {
+            env::set_var("MANETTE_TEST_COMPLETION", "/tmp/completion");
+            let command_args =
+                build_command_arguments("echo 'a  b' &&\\\n cat \"$MANETTE_TEST_COMPLETION\"/fo");
+            let completion = path_full_completion(
+                &command_args,
+                Some("/tmp/completion/"),
+                "/tmp/completion/foo",
+            );
+            assert_eq!(
+                completion,
+                "echo 'a  b' &&\\\n cat \"$MANETTE_TEST_COMPLETION\"/foo"
+            );
+
+            let command_args = build_command_arguments("ls ~/do");
+            let home = format!("{}/", userenv::home());
+            let completion =
+                path_full_completion(&command_args, Some(&home), &format!("{}My Docs/", home));
+            assert_eq!(completion, "ls ~/My\\ Docs/");
+
+            // An unclosed quote cannot be kept, the path is quoted again.
+            let command_args = build_command_arguments("cat \"a b/c");
+            let completion = path_full_completion(&command_args, Some("a b/"), "a b/cd");
+            assert_eq!(completion, "cat a\\ b/cd");
+        }
+
         #[test]
         fn test_directory_from_path() {
             assert_eq!(
@@ -35,48 +35,426 @@ knowledge of the CeCILL license and that you accept its terms.
 */
 
 pub mod run {
-    use cursive::{Cursive, CursiveExt};
+    use cursive::theme::PaletteColor;
+    use cursive::view::View;
+    use cursive::{CbSink, Cursive, CursiveExt};
 
+    use crate::command::{fullscreen, job};
+    use crate::executables::executables;
     use crate::file::filetype;
     use crate::file::filetype::FileType;
     use crate::file::open;
+    use crate::fileviewerview::FileViewer;
+    use crate::hexviewerview::HexViewer;
+    use crate::lexer::lexer;
+    use crate::ls::ls::{self, LsOptions, SortMode};
+    use crate::preview::preview;
+    use crate::terminalview::TerminalView;
     use crate::ui::update;
     use crate::userenv::userenv;
+    use crate::watcher::watcher::DirectoryWatcher;
+    use crate::RunState;
 
-    use std::fs;
-    use std::path::Path;
-    use std::process::{Command, Output};
+    use std::collections::hash_map::RandomState;
+    use std::collections::HashSet;
+    use std::fs::OpenOptions;
+    use std::hash::{BuildHasher, Hasher};
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::process::{CommandExt, ExitStatusExt};
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, ExitStatus, Stdio};
+    use std::thread;
+    use std::time::{Duration, Instant, SystemTime};
     use std::{cmp::Ordering, env};
+    use std::{fmt, fs};
+
+    // Commands handled by manette itself.
+    pub const BUILTINS: [&str; 8] = ["cd", "ls", "jobs", "fg", "kill", "term", "view", "hexview"];
+
+    // Time given to a cancelled command to exit before sending the next signal.
+    const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(2);
 
     pub fn run_command(command: &str, s: &mut Cursive) {
-        let tokens: Vec<&str> = command.split_whitespace().collect();
         log::debug!("Running command {}", command);
+        update::notify(s, "");
+        // The command may install or remove programs.
+        executables::invalidate();
+        if let Some(command) = fullscreen::forced_command(command) {
+            run_detached_command("/bin/sh", vec!["-c", command], s);
+            return;
+        }
+        if let Some(command) = job::background_command(command) {
+            spawn_command(command, s, true);
+            return;
+        }
+        let tokens = match lexer::split(command) {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                update::show_error(s, format!("Cannot parse command: {}", error));
+                return;
+            }
+        };
+        let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
         match tokens.is_empty() {
             true => update::show_error(s, "Please enter a command.".to_string()),
             false => match tokens[0] {
                 "cd" => run_cd(tokens[1..].to_vec(), s),
                 "ls" => run_ls(tokens[1..].to_vec(), s),
-                "emacs" | "vim" | "less" => {
-                    run_detached_command(tokens[0], tokens[1..].to_vec(), s)
+                "jobs" => run_jobs(s),
+                "fg" => run_fg(tokens[1..].to_vec(), s),
+                "kill" if tokens.len() == 1 => cancel_command(s),
+                "kill" if tokens[1..].iter().all(|arg| arg.starts_with('%')) => {
+                    run_kill(tokens[1..].to_vec(), s)
                 }
-                _ => {
-                    let output = Command::new("/bin/sh").arg("-c").arg(command).output();
-                    match output {
-                        Ok(output) => {
-                            log::debug!("Completed command {} with result {:?}", command, output);
-                            let result = CommandResult::from_output(output);
-                            update::command_output(s, result);
-                        }
-                        Err(output) => {
-                            log::error!("Error running {} with result {:?}", command, output);
+                "view" => {
+                    let background = s.current_theme().palette[PaletteColor::View];
+                    let open = |path: &str| FileViewer::open(path, background);
+                    run_viewer(tokens[1..].to_vec(), open, s)
+                }
+                "hexview" => run_viewer(tokens[1..].to_vec(), HexViewer::open, s),
+                "term" if tokens.len() > 1 => run_terminal_command(term_arguments(command), s),
+                _ if fullscreen::needs_full_terminal(&tokens) => {
+                    run_detached_command("/bin/sh", vec!["-c", command], s)
+                }
+                _ => spawn_command(command, s, false),
+            },
+        }
+    }
+
+    // Arguments of term as typed, from the offset of the first one, which
+    // is right whatever quoting or expansion the term word used.
+    fn term_arguments(command: &str) -> &str {
+        match lexer::tokenize(command) {
+            Ok(tokens) if tokens.len() > 1 => &command[tokens[1].start..],
+            _ => "",
+        }
+    }
+
+    // Runs the command without blocking the UI, streaming its output to
+    // the command views as it is produced unless it runs in the background.
+    fn spawn_command(command: &str, s: &mut Cursive, background: bool) {
+        let started = Instant::now();
+        let child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn();
+        match child {
+            Ok(mut child) => {
+                let pid = child.id();
+                if !background {
+                    update::command_output(s);
+                }
+                let command_id = s
+                    .with_user_data(|state: &mut RunState| state.jobs.add(command, pid, background))
+                    .unwrap_or(0);
+                if background {
+                    update::notify(s, &format!("[{}] Started {}", command_id, command));
+                }
+                refresh_title(s);
+                let sink = s.cb_sink().clone();
+                if let Some(stdout) = child.stdout.take() {
+                    stream_output(stdout, command_id, sink.clone(), job::Stream::Output);
+                }
+                if let Some(stderr) = child.stderr.take() {
+                    stream_output(stderr, command_id, sink, job::Stream::Error);
+                }
+                let command = command.to_string();
+                let sink = s.cb_sink().clone();
+                thread::spawn(move || match child.wait() {
+                    Ok(status) => {
+                        log::debug!("Completed command {} with status {:?}", command, status);
+                        let duration = started.elapsed();
+                        let _ = sink.send(Box::new(move |s| {
+                            complete_command(s, command_id, |cancelled| {
+                                CommandResult::from_status(status, duration, cancelled)
+                            })
+                        }));
+                    }
+                    Err(error) => {
+                        log::error!("Error waiting for {}: {:?}", command, error);
+                    }
+                });
+            }
+            Err(error) => {
+                log::error!("Error running {} with result {:?}", command, error);
+                update::show_error(s, format!("Error running {}: {}", command, error));
+            }
+        }
+    }
+
+    // Reads the stream line by line in its own thread and hands every line
+    // to the UI thread, which keeps it with the job and displays it if the
+    // job is in the foreground.
+    fn stream_output<R>(stream: R, command_id: usize, sink: CbSink, kind: job::Stream)
+    where
+        R: Read + Send + 'static,
+    {
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stream);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        let text = String::from_utf8_lossy(&line).into_owned();
+                        let sent = sink.send(Box::new(move |s| {
+                            s.with_user_data(|state: &mut RunState| {
+                                state.jobs.append(command_id, kind, &text)
+                            });
+                            if is_current_command(s, command_id) {
+                                match kind {
+                                    job::Stream::Output => update::append_output(s, &text),
+                                    job::Stream::Error => update::append_error(s, &text),
+                                }
+                            }
+                        }));
+                        if sent.is_err() {
+                            break;
                         }
                     }
+                    Err(error) => {
+                        log::error!("Cannot read command output: {:?}", error);
+                        break;
+                    }
                 }
-            },
+            }
+        });
+    }
+
+    // Records the result of a completed job: it is displayed if the job is
+    // in the foreground, otherwise the user is notified and the job is kept
+    // until its output is retrieved.
+    fn complete_command<F>(s: &mut Cursive, command_id: usize, result: F)
+    where
+        F: FnOnce(bool) -> CommandResult,
+    {
+        let cancelled = s
+            .with_user_data(|state: &mut RunState| {
+                state.jobs.get(command_id).map(|job| job.cancelled)
+            })
+            .flatten();
+        let result = match cancelled {
+            Some(cancelled) => result(cancelled),
+            None => return,
+        };
+        if is_current_command(s, command_id) {
+            s.with_user_data(|state: &mut RunState| state.jobs.remove(command_id));
+            update::command_result(s, &result);
+        } else {
+            let message = s
+                .with_user_data(|state: &mut RunState| {
+                    state.jobs.finish(command_id, result);
+                    state.jobs.get(command_id).map(|job| job.to_string())
+                })
+                .flatten();
+            if let Some(message) = message {
+                update::notify(s, &message);
+            }
+        }
+        refresh_title(s);
+    }
+
+    // Runs the command in an embedded terminal, for interactive programs
+    // which need their input to be a TTY.
+    pub fn run_terminal_command(command: &str, s: &mut Cursive) {
+        let started = Instant::now();
+        let sink = s.cb_sink().clone();
+        let on_exit = move |pid: Option<u32>, status: portable_pty::ExitStatus| {
+            log::debug!("Terminal command exited with status {:?}", status);
+            let duration = started.elapsed();
+            let _ = sink.send(Box::new(move |s| {
+                let command_id = s
+                    .with_user_data(|state: &mut RunState| state.jobs.find_pid(pid?))
+                    .flatten();
+                if let Some(command_id) = command_id {
+                    if is_current_command(s, command_id) {
+                        update::focus_command_line(s);
+                    }
+                    complete_command(s, command_id, |cancelled| {
+                        CommandResult::from_pty_status(status, duration, cancelled)
+                    });
+                }
+            }));
+        };
+        match TerminalView::spawn(command, s.cb_sink().clone(), on_exit) {
+            Ok(view) => {
+                let pid = view.pid();
+                update::command_terminal(s, view);
+                if let Some(pid) = pid {
+                    s.with_user_data(|state: &mut RunState| state.jobs.add(command, pid, false));
+                }
+                refresh_title(s);
+            }
+            Err(error) => {
+                log::error!("Error running {} in terminal: {:?}", command, error);
+                update::show_error(s, format!("Error running {}: {}", command, error));
+            }
+        }
+    }
+
+    fn is_current_command(s: &mut Cursive, command_id: usize) -> bool {
+        s.with_user_data(|state: &mut RunState| state.jobs.is_foreground(command_id))
+            .unwrap_or(true)
+    }
+
+    fn refresh_title(s: &mut Cursive) {
+        let (running, options) = s
+            .with_user_data(|state: &mut RunState| (state.jobs.running_count(), state.ls.clone()))
+            .unwrap_or_default();
+        let mut title = match running {
+            0 => String::from("manette"),
+            1 => String::from("manette [1 job]"),
+            _ => format!("manette [{} jobs]", running),
+        };
+        if options.show_hidden {
+            title.push_str(" [hidden files]");
+        }
+        if options.sort != SortMode::Name {
+            title.push_str(&format!(" [by {}]", options.sort));
+        }
+        if options.reverse {
+            title.push_str(" [reversed]");
+        }
+        update::title(s, &title);
+    }
+
+    fn run_jobs(s: &mut Cursive) {
+        let jobs = s
+            .with_user_data(|state: &mut RunState| {
+                state
+                    .jobs
+                    .iter()
+                    .map(|job| format!("{}\n", job))
+                    .collect::<String>()
+            })
+            .unwrap_or_default();
+        update::command_output(s);
+        match jobs.is_empty() {
+            true => update::append_output(s, "No jobs\n"),
+            false => update::append_output(s, &jobs),
+        }
+    }
+
+    // Brings a job to the foreground, displaying the output it produced so
+    // far, and the rest as it comes.
+    fn run_fg(params: Vec<&str>, s: &mut Cursive) {
+        let command_id = match params.first() {
+            Some(spec) => job::parse_spec(spec),
+            None => s
+                .with_user_data(|state: &mut RunState| state.jobs.last().map(|job| job.id))
+                .flatten(),
+        };
+        let command_id = match command_id {
+            Some(command_id) => command_id,
+            None => {
+                update::show_error(s, "No such job".to_string());
+                return;
+            }
+        };
+        let job = s
+            .with_user_data(|state: &mut RunState| {
+                let job = state.jobs.get(command_id)?;
+                let output = (job.output.clone(), job.error_output.clone());
+                match job.is_running() {
+                    true => Some((output, None)),
+                    false => Some((output, state.jobs.remove(command_id)?.result)),
+                }
+            })
+            .flatten();
+        match job {
+            Some(((output, error_output), result)) => {
+                update::command_output(s);
+                update::append_output(s, &output);
+                update::append_error(s, &error_output);
+                match result {
+                    Some(result) => update::command_result(s, &result),
+                    None => {
+                        s.with_user_data(|state: &mut RunState| {
+                            state.jobs.set_foreground(command_id)
+                        });
+                    }
+                }
+                refresh_title(s);
+            }
+            None => update::show_error(s, format!("No such job: {}", command_id)),
+        }
+    }
+
+    fn run_kill(params: Vec<&str>, s: &mut Cursive) {
+        for spec in params {
+            match job::parse_spec(spec) {
+                Some(command_id) => cancel_job(s, command_id),
+                None => update::show_error(s, format!("Invalid job: {}", spec)),
+            }
+        }
+    }
+
+    /// Cancels the foreground command: it is first interrupted, then
+    /// terminated and finally killed if it is still running after each
+    /// grace period.
+    pub fn cancel_command(s: &mut Cursive) {
+        let command_id = s
+            .with_user_data(|state: &mut RunState| state.jobs.foreground().map(|job| job.id))
+            .flatten();
+        match command_id {
+            Some(command_id) => cancel_job(s, command_id),
+            None => log::debug!("No running command to cancel"),
+        }
+    }
+
+    fn cancel_job(s: &mut Cursive, command_id: usize) {
+        let running = s
+            .with_user_data(|state: &mut RunState| {
+                let job = state.jobs.get_mut(command_id)?;
+                job.cancelled = true;
+                Some(job.is_running())
+            })
+            .flatten();
+        match running {
+            Some(true) => send_cancel_signal(s, command_id, 0),
+            Some(false) => log::debug!("Job {} already completed", command_id),
+            None => update::show_error(s, format!("No such job: {}", command_id)),
+        }
+    }
+
+    fn send_cancel_signal(s: &mut Cursive, command_id: usize, attempt: usize) {
+        let signal = match job::CANCEL_SIGNALS.get(attempt) {
+            Some(signal) => *signal,
+            None => return,
+        };
+        let result = s.with_user_data(|state: &mut RunState| {
+            state
+                .jobs
+                .get(command_id)
+                .filter(|job| job.is_running())
+                .map(|job| (job.command.clone(), job::signal(job, signal)))
+        });
+        match result.flatten() {
+            Some((command, Ok(()))) => {
+                log::info!("Sent signal {} to {}", signal, command);
+                let sink = s.cb_sink().clone();
+                thread::spawn(move || {
+                    thread::sleep(CANCEL_GRACE_PERIOD);
+                    let _ = sink.send(Box::new(move |s| {
+                        send_cancel_signal(s, command_id, attempt + 1)
+                    }));
+                });
+            }
+            Some((command, Err(error))) => {
+                log::error!("Cannot send signal {} to {}: {:?}", signal, command, error);
+            }
+            // The command already completed.
+            None => (),
         }
     }
 
-    fn run_detached_command(command: &str, params: Vec<&str>, s: &mut Cursive) {
+    pub fn run_detached_command(command: &str, params: Vec<&str>, s: &mut Cursive) {
         update::clear_command(s);
         s.quit();
         let exit_status = Command::new(command).args(params).status();
@@ -118,27 +496,46 @@ pub mod run {
         }
     }
 
+    /// Previews the file beside the file list. The preview is made in the
+    /// background and dropped if another file was selected meanwhile.
+    pub fn show_preview(s: &mut Cursive, filename: &str) {
+        let previewed = (filename.to_string(), modified(filename));
+        let (generation, show_hidden, same_file) = s
+            .with_user_data(|state: &mut RunState| {
+                state.preview_generation += 1;
+                let same_file = matches!(&state.previewed, Some((path, _)) if path == filename);
+                state.previewed = Some(previewed);
+                (state.preview_generation, state.ls.show_hidden, same_file)
+            })
+            .unwrap_or((0, false, false));
+        let filename = filename.to_string();
+        let sink = s.cb_sink().clone();
+        thread::spawn(move || {
+            let content = preview::preview(Path::new(&filename), show_hidden);
+            let sent = sink.send(Box::new(move |s| {
+                let current = s
+                    .with_user_data(|state: &mut RunState| state.preview_generation)
+                    .unwrap_or(0);
+                if current == generation {
+                    // The same file previewed again keeps its scroll position.
+                    update::file_preview(s, &filename, content, !same_file);
+                }
+            }));
+            if let Err(error) = sent {
+                log::error!("Cannot show the preview: {:?}", error);
+            }
+        });
+    }
+
     pub fn edit_file(s: &mut Cursive, filename: &str) {
         let metadata = fs::metadata(filename);
 
         match metadata {
             Ok(metadata) => {
                 if metadata.is_file() {
-                    let editor = userenv::editor();
-                    let tokens: Vec<&str> = editor.split_whitespace().collect();
-                    let command = match tokens.is_empty() {
-                        true => "vim",
-                        false => tokens[0],
-                    };
-                    let args = match tokens.is_empty() {
-                        true => vec![filename],
-                        false => {
-                            let mut all_args = tokens[1..].to_vec();
-                            all_args.push(filename);
-                            all_args
-                        }
-                    };
-                    run_detached_command(command, args, s);
+                    let (command, mut args) = editor_command();
+                    args.push(filename.to_string());
+                    run_detached_command(&command, args.iter().map(String::as_str).collect(), s);
                 }
             }
             Err(error) => {
@@ -148,6 +545,79 @@ pub mod run {
         }
     }
 
+    /// Edits the command line in the user editor, loading the saved
+    /// command back in the command line.
+    pub fn edit_command_line(s: &mut Cursive, content: &str) {
+        let path = match write_temp_file("manette-command-", ".sh", content) {
+            Ok(path) => path,
+            Err(error) => {
+                update::show_error(s, format!("Cannot write the command: {}", error));
+                return;
+            }
+        };
+        // The editor runs while the UI is stopped, the saved command is
+        // loaded once the UI runs again.
+        let edited_path = path.clone();
+        let loaded = s.cb_sink().send(Box::new(move |s| {
+            let edited = fs::read_to_string(&edited_path);
+            if let Err(error) = fs::remove_file(&edited_path) {
+                log::error!("Cannot remove {:?}: {:?}", edited_path, error);
+            }
+            match edited {
+                // Editors end the file with a new line.
+                Ok(edited) => update::set_command_line(s, edited.trim_end_matches('\n')),
+                Err(error) => update::show_error(
+                    s,
+                    format!("Cannot read {}: {}", edited_path.display(), error),
+                ),
+            }
+        }));
+        if let Err(error) = loaded {
+            log::error!("Cannot load edited command: {:?}", error);
+            return;
+        }
+        let (command, mut args) = editor_command();
+        args.push(path.to_string_lossy().to_string());
+        run_detached_command(&command, args.iter().map(String::as_str).collect(), s);
+    }
+
+    // Writes the content to a new file of the temporary directory, only
+    // readable by the user. The file gets a random name and is never an
+    // existing file, so other users cannot make it overwrite theirs.
+    fn write_temp_file(prefix: &str, suffix: &str, content: &str) -> io::Result<PathBuf> {
+        let mut attempts = 0;
+        loop {
+            let random = RandomState::new().build_hasher().finish();
+            let path = env::temp_dir().join(format!("{}{:016x}{}", prefix, random, suffix));
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path);
+            match file {
+                Ok(mut file) => {
+                    if let Err(error) = file.write_all(content.as_bytes()) {
+                        let _ = fs::remove_file(&path);
+                        return Err(error);
+                    }
+                    return Ok(path);
+                }
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists && attempts < 100 => {
+                    attempts += 1
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    // Editor program and its arguments, from $EDITOR.
+    fn editor_command() -> (String, Vec<String>) {
+        let editor = userenv::editor();
+        let mut tokens = editor.split_whitespace().map(String::from);
+        let command = tokens.next().unwrap_or_else(|| String::from("vim"));
+        (command, tokens.collect())
+    }
+
     fn run_cd(params: Vec<&str>, s: &mut Cursive) {
         log::debug!("Running cd to {:?}", &params);
         match params.first() {
@@ -172,96 +642,405 @@ pub mod run {
         }
     }
 
-    fn run_ls(params: Vec<&str>, s: &mut Cursive) {
-        let dir = match params.len() {
-            0 => "./",
-            1 => match params.first() {
-                Some(param) => param,
-                None => {
-                    update::show_error(s, "Please provide one argument".to_string());
-                    return;
-                }
-            },
+    // Shows the file given to view or hexview in the viewer.
+    fn run_viewer<V, F>(params: Vec<&str>, open: F, s: &mut Cursive)
+    where
+        V: View,
+        F: FnOnce(&str) -> io::Result<V>,
+    {
+        let path = match params.as_slice() {
+            [path] => path,
             _ => {
-                update::show_error(s, "Please provide one argument".to_string());
+                update::show_error(s, "Please provide a file to view".to_string());
                 return;
             }
         };
-        match fs::read_dir(dir) {
-            Ok(paths) => {
-                let mut path_strings: Vec<FileEntry> = Vec::new();
-
-                for entry in paths {
-                    match entry {
-                        Ok(entry) => {
-                            let path = entry.path();
-                            let path = match path.strip_prefix(dir) {
-                                Ok(path_without_prefix) => path_without_prefix.to_path_buf(),
-                                Err(error) => {
-                                    log::error!(
-                                        "Cannot remove prefix from {:?}: {:?}",
-                                        path,
-                                        error
-                                    );
-                                    path
-                                }
-                            };
-                            let path = path.to_str();
-                            match path {
-                                Some(path) => {
-                                    let filetype: FileType = filetype::get_type(entry);
-                                    path_strings.push(FileEntry {
-                                        filename: path.to_string(),
-                                        filetype,
-                                    });
-                                }
-                                None => {
-                                    update::show_error(
-                                        s,
-                                        format!("Error converting path to string: {:?}", entry),
-                                    );
-                                }
-                            }
-                        }
-                        Err(error) => {
-                            update::show_error(s, format!("Error listing path: {:?}", error));
-                        }
-                    }
-                }
+        match open(path) {
+            Ok(viewer) => update::file_viewer(s, viewer),
+            Err(error) => {
+                log::error!("Cannot open {}: {:?}", path, error);
+                update::show_error(s, format!("Cannot open {}: {}", path, error));
+            }
+        }
+    }
 
-                path_strings.sort();
-                match env::current_dir() {
-                    Ok(current_dir) => {
-                        if current_dir != Path::new("/").to_path_buf() {
-                            path_strings.insert(
-                                0,
-                                FileEntry {
-                                    filename: "..".to_string(),
-                                    filetype: FileType::Directory,
-                                },
-                            );
-                        }
-                    }
-                    Err(error) => log::error!("Cannot get current directory: {:?}", error),
+    /// Closes the file viewer, going back to the file list.
+    pub fn close_viewer(s: &mut Cursive) {
+        list_again(s);
+    }
+
+    fn run_ls(params: Vec<&str>, s: &mut Cursive) {
+        let defaults = s
+            .with_user_data(|state: &mut RunState| state.ls.clone())
+            .unwrap_or_default();
+        let (options, paths) = match ls::parse_args(&params, &defaults) {
+            Ok(parsed) => parsed,
+            // The system ls handles the options the builtin does not.
+            Err(error) => {
+                log::debug!("{}, running the system ls", error);
+                let args: Vec<String> = ls::expand_globs(&params)
+                    .iter()
+                    .map(|arg| lexer::quote(arg))
+                    .collect();
+                spawn_command(&format!("ls {}", args.join(" ")), s, false);
+                update::notify(s, &format!("{}, running the system ls", error));
+                return;
+            }
+        };
+        let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+        list(s, options, paths);
+    }
+
+    // Lists the paths, keeping the options and the paths to list them
+    // again.
+    fn list(s: &mut Cursive, options: LsOptions, paths: Vec<String>) {
+        let (listings, errors) = collect_listings(&options, &paths);
+        for error in &errors {
+            log::error!("{}", error);
+        }
+        if listings.is_empty() {
+            update::show_error(s, errors.join("\n"));
+            return;
+        }
+        let long = options.long;
+        s.with_user_data(|state: &mut RunState| state.listed = Some((options, paths)));
+        watch_listings(s, &listings);
+        update::file_list_view(s, listings, long);
+        if let Some(selection) = update::file_list_selection(s) {
+            show_preview(s, &selection);
+        }
+        if !errors.is_empty() {
+            update::notify(s, &errors.join("; "));
+        }
+    }
+
+    // Reads the files to list, with the errors met.
+    fn collect_listings(options: &LsOptions, paths: &[String]) -> (Vec<Listing>, Vec<String>) {
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        let mut expanded_paths = ls::expand_globs(&paths);
+        if expanded_paths.is_empty() {
+            expanded_paths.push(String::from("."));
+        }
+        let mut errors = Vec::new();
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        for path in &expanded_paths {
+            match fs::metadata(path) {
+                Ok(metadata) if metadata.is_dir() && !options.directories => dirs.push(path),
+                Ok(metadata) => files.push(FileEntry {
+                    filename: path.to_string(),
+                    filetype: filetype::get_type_from_metadata(metadata.clone()),
+                    metadata: Some(metadata),
+                }),
+                Err(error) => errors.push(format!("Cannot access {}: {}", path, error)),
+            }
+        }
+        // Directories get a heading when they are not listed alone.
+        let headings = expanded_paths.len() > 1 || options.recursive;
+        let mut listings = Vec::new();
+        if !files.is_empty() {
+            ls::sort(&mut files, options);
+            listings.push(Listing {
+                heading: None,
+                directory: None,
+                entries: files,
+            });
+        }
+        for dir in dirs {
+            list_directory(dir, options, headings, &mut listings, &mut errors);
+        }
+        (listings, errors)
+    }
+
+    /// Lists the last listed paths again after their files changed,
+    /// keeping the selection and the scroll position.
+    pub fn refresh_listing(s: &mut Cursive) {
+        if !update::has_file_list(s) {
+            return;
+        }
+        let (options, paths) = match s
+            .with_user_data(|state: &mut RunState| state.listed.clone())
+            .flatten()
+        {
+            Some(listed) => listed,
+            None => return,
+        };
+        let (listings, errors) = collect_listings(&options, &paths);
+        for error in &errors {
+            log::debug!("{}", error);
+        }
+        watch_listings(s, &listings);
+        update::refresh_file_list(s, listings, options.long);
+        if let Some(selection) = update::file_list_selection(s) {
+            let previewed = (selection.clone(), modified(&selection));
+            let changed = s
+                .with_user_data(|state: &mut RunState| state.previewed.as_ref() != Some(&previewed))
+                .unwrap_or(true);
+            if changed {
+                show_preview(s, &selection);
+            }
+        }
+    }
+
+    // Modification time of the file, to tell whether its preview is stale.
+    fn modified(filename: &str) -> Option<SystemTime> {
+        fs::metadata(filename)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    // Watches the listed directories, to refresh the listing when their
+    // files change.
+    fn watch_listings(s: &mut Cursive, listings: &[Listing]) {
+        let dirs: Vec<PathBuf> = listings
+            .iter()
+            .filter_map(|listing| listing.directory.as_ref())
+            .filter_map(|dir| fs::canonicalize(dir).ok())
+            .collect();
+        let sink = s.cb_sink().clone();
+        s.with_user_data(|state: &mut RunState| {
+            if state.watcher.is_none() {
+                match DirectoryWatcher::new(sink, refresh_listing) {
+                    Ok(watcher) => state.watcher = Some(watcher),
+                    Err(error) => log::error!("Cannot watch the listed files: {:?}", error),
                 }
-                update::file_list_view(s, path_strings);
             }
+            if let Some(watcher) = &mut state.watcher {
+                watcher.watch(dirs);
+            }
+        });
+    }
+
+    // Lists the files of the directory, then its subdirectories when
+    // listing recursively.
+    fn list_directory(
+        dir: &str,
+        options: &LsOptions,
+        heading: bool,
+        listings: &mut Vec<Listing>,
+        errors: &mut Vec<String>,
+    ) {
+        let dir_entries = match fs::read_dir(dir) {
+            Ok(dir_entries) => dir_entries,
             Err(error) => {
-                update::show_error(s, error.to_string());
+                errors.push(format!("Cannot list {}: {}", dir, error));
+                return;
+            }
+        };
+        let mut entries = Vec::new();
+        let mut subdirectories = HashSet::new();
+        for entry in dir_entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    errors.push(format!("Error listing {}: {}", dir, error));
+                    continue;
+                }
+            };
+            let filename = match entry.file_name().into_string() {
+                Ok(filename) => filename,
+                Err(filename) => {
+                    errors.push(format!("Error converting path to string: {:?}", filename));
+                    continue;
+                }
+            };
+            if filename.starts_with('.') && !options.show_hidden {
+                continue;
             }
+            // Links to directories are not followed, to avoid loops.
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            if options.recursive && is_dir {
+                subdirectories.insert(filename.clone());
+            }
+            let metadata = entry.metadata().ok();
+            entries.push(FileEntry {
+                filename,
+                filetype: filetype::get_type(entry),
+                metadata,
+            });
+        }
+        ls::sort(&mut entries, options);
+        let special_entry = |filename: &str| FileEntry {
+            filename: filename.to_string(),
+            filetype: FileType::Directory,
+            metadata: fs::metadata(Path::new(dir).join(filename)).ok(),
+        };
+        // The parent directory is kept to navigate to it.
+        let is_root = fs::canonicalize(dir)
+            .map(|path| path == Path::new("/"))
+            .unwrap_or(false);
+        if options.dot_entries || (!heading && !is_root) {
+            entries.insert(0, special_entry(".."));
+        }
+        if options.dot_entries {
+            entries.insert(0, special_entry("."));
+        }
+        let listing = Listing {
+            heading: match heading {
+                true => Some(dir.to_string()),
+                false => None,
+            },
+            directory: Some(dir.to_string()),
+            entries,
+        };
+        let subdirectories: Vec<String> = listing
+            .entries
+            .iter()
+            .filter(|entry| subdirectories.contains(&entry.filename))
+            .map(|entry| listing.path(entry))
+            .collect();
+        listings.push(listing);
+        for subdirectory in subdirectories {
+            list_directory(&subdirectory, options, heading, listings, errors);
         }
     }
 
     pub struct CommandResult {
-        pub output: String,
-        pub error_output: String,
+        pub exit_code: Option<i32>,
+        // Signal which terminated the command, if any.
+        pub signal: Option<i32>,
+        pub duration: Duration,
+        pub cancelled: bool,
     }
 
     impl CommandResult {
-        fn from_output(output: Output) -> CommandResult {
+        fn from_status(status: ExitStatus, duration: Duration, cancelled: bool) -> CommandResult {
+            CommandResult {
+                exit_code: status.code(),
+                signal: status.signal(),
+                duration,
+                cancelled,
+            }
+        }
+
+        fn from_pty_status(
+            status: portable_pty::ExitStatus,
+            duration: Duration,
+            cancelled: bool,
+        ) -> CommandResult {
+            // The pty only gives the description of the signal, like
+            // "Terminated by Killed", which is mapped back to its number.
+            let signal = status
+                .to_string()
+                .strip_prefix("Terminated by ")
+                .and_then(job::signal_from_description);
             CommandResult {
-                output: String::from_utf8(output.stdout).unwrap(),
-                error_output: String::from_utf8(output.stderr).unwrap(),
+                exit_code: match signal {
+                    Some(_) => None,
+                    None => Some(status.exit_code() as i32),
+                },
+                signal,
+                duration,
+                cancelled,
+            }
+        }
+
+        pub fn success(&self) -> bool {
+            self.exit_code == Some(0)
+        }
+    }
+
+    impl fmt::Display for CommandResult {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match (self.exit_code, self.signal) {
+                (Some(code), _) => write!(f, "exit code {}", code)?,
+                (None, Some(signal)) => write!(f, "killed by {}", job::signal_name(signal))?,
+                (None, None) => write!(f, "unknown status")?,
+            }
+            write!(f, " in {:.2}s", self.duration.as_secs_f64())?;
+            if self.cancelled {
+                write!(f, " (cancelled)")?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Switches between the short and the long listing for the session.
+    pub fn toggle_long_listing(s: &mut Cursive) {
+        let long = !listed_options(s).long;
+        change_options(s, |options| options.long = long);
+        list_again(s);
+    }
+
+    /// Shows or hides the files starting with a dot for the session.
+    pub fn toggle_hidden_files(s: &mut Cursive) {
+        let show_hidden = !listed_options(s).show_hidden;
+        change_options(s, |options| options.show_hidden = show_hidden);
+        refresh_title(s);
+        list_again(s);
+    }
+
+    /// Sorts the listings by the next sort mode for the session.
+    pub fn cycle_sort_mode(s: &mut Cursive) {
+        let sort = listed_options(s).sort.next();
+        change_options(s, |options| options.sort = sort);
+        refresh_title(s);
+        list_again(s);
+    }
+
+    pub fn toggle_reverse_sort(s: &mut Cursive) {
+        let reverse = !listed_options(s).reverse;
+        change_options(s, |options| options.reverse = reverse);
+        refresh_title(s);
+        list_again(s);
+    }
+
+    pub fn toggle_directories_first(s: &mut Cursive) {
+        let directories_first = !listed_options(s).directories_first;
+        change_options(s, |options| options.directories_first = directories_first);
+        list_again(s);
+    }
+
+    // Options of the last listing, or of the session when nothing was
+    // listed yet.
+    fn listed_options(s: &mut Cursive) -> LsOptions {
+        s.with_user_data(|state: &mut RunState| match &state.listed {
+            Some((options, _)) => options.clone(),
+            None => state.ls.clone(),
+        })
+        .unwrap_or_default()
+    }
+
+    // Changes an option for the session and for the last listing.
+    fn change_options<F: Fn(&mut LsOptions)>(s: &mut Cursive, change: F) {
+        s.with_user_data(|state: &mut RunState| {
+            change(&mut state.ls);
+            if let Some((options, _)) = &mut state.listed {
+                change(options);
+            }
+        });
+    }
+
+    // Lists the paths of the last listing again, with its options, or the
+    // current directory when nothing was listed yet.
+    fn list_again(s: &mut Cursive) {
+        let (options, paths) = s
+            .with_user_data(|state: &mut RunState| match &state.listed {
+                Some(listed) => listed.clone(),
+                None => (state.ls.clone(), Vec::new()),
+            })
+            .unwrap_or_default();
+        list(s, options, paths);
+    }
+
+    /// Files of a directory listed by ls, or the files given to it.
+    pub struct Listing {
+        // Directory shown above the files when listing several.
+        pub heading: Option<String>,
+        // Directory containing the files, None for the files given to ls.
+        pub directory: Option<String>,
+        pub entries: Vec<FileEntry>,
+    }
+
+    impl Listing {
+        /// Path of the entry from the current directory.
+        pub fn path(&self, entry: &FileEntry) -> String {
+            match &self.directory {
+                Some(dir) if dir != "." && dir != "./" => Path::new(dir)
+                    .join(&entry.filename)
+                    .to_string_lossy()
+                    .into_owned(),
+                _ => entry.filename.clone(),
             }
         }
     }
@@ -269,11 +1048,12 @@ pub mod run {
     pub struct FileEntry {
         pub filename: String,
         pub filetype: FileType,
+        pub metadata: Option<fs::Metadata>,
     }
 
     impl Ord for FileEntry {
         fn cmp(&self, other: &Self) -> Ordering {
-            self.filename.cmp(&other.filename)
+            ls::compare_names(&self.filename, &other.filename)
         }
     }
 
@@ -302,5 +1082,527 @@ pub mod run {
             let test_params = vec!["."];
             run_ls(test_params, &mut test_cursive);
         }
+
+        #[test]
+        fn test_refresh_listing() {
+            use cursive::view::Nameable;
+            use cursive::views::{LinearLayout, OnEventView, ScrollView, SelectView};
+            let dir = env::temp_dir().join(format!("manette-test-refresh-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("file"), "").unwrap();
+            let mut test_cursive = cursive::dummy();
+            test_cursive.set_user_data(RunState::new());
+            test_cursive.add_layer(LinearLayout::vertical().with_name("command_layout"));
+            let dir_name = dir.display().to_string();
+            run_ls(vec!["-la", &dir_name], &mut test_cursive);
+
+            fs::write(dir.join(".hidden"), "").unwrap();
+            refresh_listing(&mut test_cursive);
+            let items = test_cursive
+                .call_on_name(
+                    "filelist_view",
+                    |view: &mut ScrollView<OnEventView<SelectView<String>>>| {
+                        let select = view.get_inner().get_inner();
+                        select
+                            .iter()
+                            .map(|(label, path)| (label.to_string(), path.clone()))
+                            .collect::<Vec<_>>()
+                    },
+                )
+                .unwrap();
+            fs::remove_dir_all(&dir).unwrap();
+            let hidden = items
+                .iter()
+                .find(|(_, path)| path.ends_with("/.hidden"))
+                .unwrap();
+            assert!(hidden.0.starts_with("-rw"), "{:?}", hidden);
+        }
+
+        #[test]
+        fn test_term_arguments() {
+            assert_eq!(term_arguments("term vim -p a b"), "vim -p a b");
+            assert_eq!(term_arguments("  \"term\"  vim | less"), "vim | less");
+            assert_eq!(term_arguments("\\term 'é' ü"), "'é' ü");
+            assert_eq!(term_arguments("term"), "");
+        }
+
+        #[test]
+        fn test_write_temp_file() {
+            use std::os::unix::fs::PermissionsExt;
+            let first = write_temp_file("manette-test-", ".sh", "ls -l").unwrap();
+            let second = write_temp_file("manette-test-", ".sh", "ls -l").unwrap();
+            assert_ne!(first, second);
+            assert_eq!(fs::read_to_string(&first).unwrap(), "ls -l");
+            let mode = fs::metadata(&first).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            fs::remove_file(first).unwrap();
+            fs::remove_file(second).unwrap();
+        }
+
+        #[test]
+        fn test_command_result() {
+            let success = CommandResult::from_status(
+                ExitStatus::from_raw(0),
+                Duration::from_millis(1500),
+                false,
+            );
+            assert!(success.success());
+            assert_eq!(success.to_string(), "exit code 0 in 1.50s");
+
+            let failure =
+                CommandResult::from_status(ExitStatus::from_raw(1 << 8), Duration::ZERO, false);
+            assert!(!failure.success());
+            assert_eq!(failure.exit_code, Some(1));
+
+            let killed = CommandResult::from_status(
+                ExitStatus::from_raw(libc::SIGINT),
+                Duration::ZERO,
+                true,
+            );
+            assert_eq!(killed.signal, Some(libc::SIGINT));
+            assert_eq!(killed.to_string(), "killed by SIGINT in 0.00s (cancelled)");
+
+            let killed = CommandResult::from_pty_status(
+                portable_pty::ExitStatus::from(ExitStatus::from_raw(libc::SIGKILL)),
+                Duration::ZERO,
+                false,
+            );
+            assert_eq!(killed.signal, Some(libc::SIGKILL));
+            assert_eq!(killed.exit_code, None);
+            let failure = CommandResult::from_pty_status(
+                portable_pty::ExitStatus::with_exit_code(2),
+                Duration::ZERO,
+                false,
+            );
+            assert_eq!((failure.exit_code, failure.signal), (Some(2), None));
+        }
+    }
+}
+
+pub mod job {
+    use crate::command::run::CommandResult;
+    use std::ffi::CStr;
+    use std::time::Instant;
+    use std::{fmt, io};
+
+    // Signals sent, in order, to a command being cancelled.
+    pub const CANCEL_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGKILL];
+
+    // Output kept for each stream of a job, in bytes.
+    pub const MAX_OUTPUT_LEN: usize = 1024 * 1024;
+
+    // Finished background jobs kept for fg, the oldest being dropped.
+    const MAX_FINISHED_JOBS: usize = 10;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Stream {
+        Output,
+        Error,
+    }
+
+    pub struct Job {
+        pub id: usize,
+        pub command: String,
+        // Process id of the shell running the command, which is also the
+        // id of its process group.
+        pub pid: u32,
+        // Whether the user asked for the job to be cancelled.
+        pub cancelled: bool,
+        pub started: Instant,
+        pub output: String,
+        pub error_output: String,
+        // Set once the command completed.
+        pub result: Option<CommandResult>,
+    }
+
+    impl Job {
+        pub fn is_running(&self) -> bool {
+            self.result.is_none()
+        }
+
+        fn append(&mut self, stream: Stream, text: &str) {
+            let buffer = match stream {
+                Stream::Output => &mut self.output,
+                Stream::Error => &mut self.error_output,
+            };
+            buffer.push_str(text);
+            let cut = output_cut(buffer, MAX_OUTPUT_LEN);
+            buffer.drain(..cut);
+        }
+    }
+
+    /// Length of the oldest output to drop to keep at most `kept` bytes,
+    /// on a character boundary.
+    pub fn output_cut(output: &str, kept: usize) -> usize {
+        if output.len() <= kept {
+            return 0;
+        }
+        let mut cut = output.len() - kept;
+        while !output.is_char_boundary(cut) {
+            cut += 1;
+        }
+        cut
+    }
+
+    impl fmt::Display for Job {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match &self.result {
+                None => write!(
+                    f,
+                    "[{}] Running {} ({}s)",
+                    self.id,
+                    self.command,
+                    self.started.elapsed().as_secs()
+                ),
+                Some(result) => write!(f, "[{}] Done {} ({})", self.id, self.command, result),
+            }
+        }
+    }
+
+    // Registry of the commands started from manette, kept until their
+    // output has been looked at, or until too many others finished.
+    #[derive(Default)]
+    pub struct Jobs {
+        jobs: Vec<Job>,
+        last_id: usize,
+        foreground: Option<usize>,
+    }
+
+    impl Jobs {
+        pub fn new() -> Self {
+            Jobs::default()
+        }
+
+        /// Registers a new running job, making it the foreground one
+        /// unless it runs in the background.
+        pub fn add(&mut self, command: &str, pid: u32, background: bool) -> usize {
+            self.last_id += 1;
+            self.jobs.push(Job {
+                id: self.last_id,
+                command: command.to_string(),
+                pid,
+                cancelled: false,
+                started: Instant::now(),
+                output: String::new(),
+                error_output: String::new(),
+                result: None,
+            });
+            if !background {
+                self.foreground = Some(self.last_id);
+            }
+            self.last_id
+        }
+
+        pub fn remove(&mut self, id: usize) -> Option<Job> {
+            let index = self.jobs.iter().position(|job| job.id == id)?;
+            Some(self.jobs.remove(index))
+        }
+
+        pub fn get(&self, id: usize) -> Option<&Job> {
+            self.jobs.iter().find(|job| job.id == id)
+        }
+
+        pub fn find_pid(&self, pid: u32) -> Option<usize> {
+            self.jobs
+                .iter()
+                .find(|job| job.pid == pid)
+                .map(|job| job.id)
+        }
+
+        pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+            self.jobs.iter_mut().find(|job| job.id == id)
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = &Job> {
+            self.jobs.iter()
+        }
+
+        /// Returns the most recently started job.
+        pub fn last(&self) -> Option<&Job> {
+            self.jobs.last()
+        }
+
+        pub fn running_count(&self) -> usize {
+            self.jobs.iter().filter(|job| job.is_running()).count()
+        }
+
+        pub fn append(&mut self, id: usize, stream: Stream, text: &str) {
+            if let Some(job) = self.get_mut(id) {
+                job.append(stream, text);
+            }
+        }
+
+        pub fn finish(&mut self, id: usize, result: CommandResult) {
+            if let Some(job) = self.get_mut(id) {
+                job.result = Some(result);
+            }
+            let finished = self.jobs.iter().filter(|job| !job.is_running()).count();
+            let mut dropped = finished.saturating_sub(MAX_FINISHED_JOBS);
+            self.jobs.retain(|job| {
+                let drop = dropped > 0 && !job.is_running();
+                if drop {
+                    dropped -= 1;
+                }
+                !drop
+            });
+        }
+
+        /// Returns the job whose output is displayed.
+        pub fn foreground(&self) -> Option<&Job> {
+            self.foreground.and_then(|id| self.get(id))
+        }
+
+        pub fn is_foreground(&self, id: usize) -> bool {
+            self.foreground == Some(id)
+        }
+
+        pub fn set_foreground(&mut self, id: usize) {
+            self.foreground = Some(id);
+        }
+
+        /// Sends the foreground job to the background, when its output
+        /// stops being displayed.
+        pub fn detach_foreground(&mut self) {
+            self.foreground = None;
+        }
+    }
+
+    /// Returns the command to run in the background if it ends with &.
+    pub fn background_command(command: &str) -> Option<&str> {
+        let command = command.trim_end();
+        let without = command.strip_suffix('&')?;
+        if without.ends_with('&') || without.ends_with('\\') || without.trim().is_empty() {
+            return None;
+        }
+        Some(without.trim_end())
+    }
+
+    /// Parses a job number, given as N or %N.
+    pub fn parse_spec(spec: &str) -> Option<usize> {
+        spec.strip_prefix('%').unwrap_or(spec).parse().ok()
+    }
+
+    pub fn signal_name(signal: libc::c_int) -> String {
+        match signal {
+            libc::SIGHUP => "SIGHUP".to_string(),
+            libc::SIGINT => "SIGINT".to_string(),
+            libc::SIGQUIT => "SIGQUIT".to_string(),
+            libc::SIGABRT => "SIGABRT".to_string(),
+            libc::SIGKILL => "SIGKILL".to_string(),
+            libc::SIGSEGV => "SIGSEGV".to_string(),
+            libc::SIGPIPE => "SIGPIPE".to_string(),
+            libc::SIGTERM => "SIGTERM".to_string(),
+            _ => format!("signal {}", signal),
+        }
+    }
+
+    /// Signal described by the text strsignal gives for it, like
+    /// "Interrupt" for SIGINT.
+    pub fn signal_from_description(description: &str) -> Option<libc::c_int> {
+        (1..libc::SIGRTMIN()).find(|signal| {
+            let text = unsafe { libc::strsignal(*signal) };
+            !text.is_null() && unsafe { CStr::from_ptr(text) }.to_bytes() == description.as_bytes()
+        })
+    }
+
+    /// Sends the signal to the whole process group of the job.
+    pub fn signal(job: &Job, signal: libc::c_int) -> io::Result<()> {
+        let result = unsafe { libc::kill(-(job.pid as libc::pid_t), signal) };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        use std::time::Duration;
+
+        #[test]
+        fn test_jobs_registry() {
+            let mut jobs = Jobs::new();
+            let first = jobs.add("make", 100, false);
+            let second = jobs.add("find /", 200, false);
+            assert!(jobs.is_foreground(second));
+            assert_eq!(jobs.foreground().unwrap().command, "find /");
+
+            assert_eq!(jobs.remove(second).unwrap().pid, 200);
+            assert!(jobs.foreground().is_none());
+            assert_eq!(jobs.get(first).unwrap().command, "make");
+            assert!(jobs.remove(second).is_none());
+
+            let third = jobs.add("sleep 10", 300, true);
+            assert!(!jobs.is_foreground(third));
+            assert_eq!(jobs.running_count(), 2);
+            assert_eq!(jobs.last().unwrap().id, third);
+        }
+
+        #[test]
+        fn test_job_output() {
+            let mut jobs = Jobs::new();
+            let id = jobs.add("make", 100, true);
+            jobs.append(id, Stream::Output, "built\n");
+            jobs.append(id, Stream::Error, "warning\n");
+            let job = jobs.get(id).unwrap();
+            assert_eq!(job.output, "built\n");
+            assert_eq!(job.error_output, "warning\n");
+
+            let long_line = "é".repeat(MAX_OUTPUT_LEN);
+            jobs.append(id, Stream::Output, &long_line);
+            let job = jobs.get(id).unwrap();
+            assert!(job.output.len() <= MAX_OUTPUT_LEN);
+            assert!(job.output.ends_with('é'));
+            assert_eq!(output_cut("built\n", 3), 3);
+            assert_eq!(output_cut("built\n", 6), 0);
+            assert_eq!(output_cut("éa", 2), 2);
+        }
+
+        #[test]
+        fn test_finished_jobs_limit() {
+            let mut jobs = Jobs::new();
+            let running = jobs.add("sleep 100", 100, true);
+            for pid in 0..MAX_FINISHED_JOBS as u32 + 2 {
+                let id = jobs.add("true", pid, true);
+                let result = CommandResult {
+                    exit_code: Some(0),
+                    signal: None,
+                    duration: Duration::from_secs(0),
+                    cancelled: false,
+                };
+                jobs.finish(id, result);
+            }
+            assert_eq!(jobs.iter().count(), MAX_FINISHED_JOBS + 1);
+            assert!(jobs.get(running).is_some());
+            assert!(jobs.get(running + 2).is_none());
+            assert!(jobs.get(running + 3).is_some());
+        }
+
+        #[test]
+        fn test_background_command() {
+            assert_eq!(background_command("make -j4 &"), Some("make -j4"));
+            assert_eq!(background_command("sleep 1&  "), Some("sleep 1"));
+            assert_eq!(background_command("make && make install"), None);
+            assert_eq!(background_command("make &&"), None);
+            assert_eq!(background_command("echo \\&"), None);
+            assert_eq!(background_command("&"), None);
+        }
+
+        #[test]
+        fn test_parse_spec() {
+            assert_eq!(parse_spec("%2"), Some(2));
+            assert_eq!(parse_spec("3"), Some(3));
+            assert_eq!(parse_spec("%a"), None);
+        }
+    }
+}
+
+pub mod fullscreen {
+    use crate::userenv::userenv;
+    use std::path::Path;
+
+    // Programs known to draw on the whole terminal.
+    const FULLSCREEN_PROGRAMS: [&str; 22] = [
+        "vi", "vim", "nvim", "emacs", "nano", "pico", "micro", "less", "more", "most", "man",
+        "top", "htop", "btop", "atop", "mc", "ranger", "nnn", "tig", "mutt", "tmux", "screen",
+    ];
+
+    // Prefix forcing a command to run with the full terminal.
+    const FORCE_PREFIX: char = '!';
+
+    /// Returns the command to run if the user forced it to use the full terminal.
+    pub fn forced_command(command: &str) -> Option<&str> {
+        command
+            .trim_start()
+            .strip_prefix(FORCE_PREFIX)
+            .map(|command| command.trim_start())
+            .filter(|command| !command.is_empty())
+    }
+
+    /// Tells whether the program run by the command needs the full terminal,
+    /// from the configured program list, the editor and the pager.
+    pub fn needs_full_terminal(tokens: &[&str]) -> bool {
+        let programs = fullscreen_programs(
+            userenv::fullscreen_programs(),
+            &userenv::editor(),
+            &userenv::pager(),
+        );
+        is_fullscreen(tokens, &programs)
+    }
+
+    fn is_fullscreen(tokens: &[&str], programs: &[String]) -> bool {
+        match program_name(tokens) {
+            Some(program) => programs.iter().any(|known| known == program),
+            None => false,
+        }
+    }
+
+    // The configured programs, or the known ones, with the editor and pager.
+    fn fullscreen_programs(configured: Option<String>, editor: &str, pager: &str) -> Vec<String> {
+        let mut programs: Vec<String> = match configured {
+            Some(programs) => programs
+                .split(':')
+                .filter(|program| !program.is_empty())
+                .map(String::from)
+                .collect(),
+            None => FULLSCREEN_PROGRAMS.iter().map(|p| p.to_string()).collect(),
+        };
+        for command in [editor, pager].iter() {
+            let tokens: Vec<&str> = command.split_whitespace().collect();
+            if let Some(program) = program_name(&tokens) {
+                programs.push(program.to_string());
+            }
+        }
+        programs
+    }
+
+    // Finds the name of the program run, skipping variable assignments
+    // and sudo.
+    fn program_name<'a>(tokens: &[&'a str]) -> Option<&'a str> {
+        let program: &'a str = tokens
+            .iter()
+            .find(|token| !token.contains('=') && **token != "sudo")?;
+        Path::new(program)
+            .file_name()
+            .and_then(|name| name.to_str())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_forced_command() {
+            assert_eq!(forced_command("!ls -l"), Some("ls -l"));
+            assert_eq!(forced_command("  ! make"), Some("make"));
+            assert_eq!(forced_command("!"), None);
+            assert_eq!(forced_command("ls"), None);
+        }
+
+        #[test]
+        fn test_program_name() {
+            assert_eq!(program_name(&["/usr/bin/htop"]), Some("htop"));
+            assert_eq!(
+                program_name(&["TERM=xterm", "sudo", "nano", "a"]),
+                Some("nano")
+            );
+            assert_eq!(program_name(&["sudo"]), None);
+        }
+
+        #[test]
+        fn test_is_fullscreen() {
+            let programs = fullscreen_programs(None, "vi", "less -R");
+            assert!(is_fullscreen(&["htop"], &programs));
+            assert!(is_fullscreen(&["/usr/bin/nano", "file"], &programs));
+            assert!(is_fullscreen(&["less", "file"], &programs));
+            assert!(!is_fullscreen(&["make", "-j4"], &programs));
+            let programs = fullscreen_programs(Some(String::from("mc::tig")), "kak", "most");
+            assert!(is_fullscreen(&["tig"], &programs));
+            assert!(is_fullscreen(&["kak", "file"], &programs));
+            assert!(is_fullscreen(&["most"], &programs));
+            assert!(!is_fullscreen(&["htop"], &programs));
+        }
     }
 }
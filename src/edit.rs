@@ -0,0 +1,298 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod edit {
+    use unicode_segmentation::UnicodeSegmentation;
+    use unicode_width::UnicodeWidthStr;
+
+    // Number of killed texts kept for yanking.
+    pub const KILL_RING_SIZE: usize = 30;
+
+    // Cursor positions are byte offsets in the content, always on grapheme
+    // boundaries.
+
+    pub fn previous_grapheme(content: &str, cursor: usize) -> usize {
+        content[..cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    }
+
+    pub fn next_grapheme(content: &str, cursor: usize) -> usize {
+        content[cursor..]
+            .graphemes(true)
+            .next()
+            .map(|grapheme| cursor + grapheme.len())
+            .unwrap_or(cursor)
+    }
+
+    // Words are the segments holding at least one alphanumeric character,
+    // as (start, end) byte offsets.
+    fn words(content: &str) -> impl DoubleEndedIterator<Item = (usize, usize)> + '_ {
+        content
+            .split_word_bound_indices()
+            .filter(|(_, segment)| segment.chars().any(char::is_alphanumeric))
+            .map(|(index, segment)| (index, index + segment.len()))
+    }
+
+    /// Start of the word before the cursor, or of the word the cursor is in.
+    pub fn previous_word_start(content: &str, cursor: usize) -> usize {
+        words(content)
+            .rev()
+            .find(|(start, _)| *start < cursor)
+            .map(|(start, _)| start)
+            .unwrap_or(0)
+    }
+
+    /// End of the word after the cursor, or of the word the cursor is in.
+    pub fn next_word_end(content: &str, cursor: usize) -> usize {
+        words(content)
+            .find(|(_, end)| *end > cursor)
+            .map(|(_, end)| end)
+            .unwrap_or(content.len())
+    }
+
+    /// Start of the whitespace delimited word before the cursor, like the
+    /// shell Ctrl-W.
+    pub fn previous_blank_word_start(content: &str, cursor: usize) -> usize {
+        let before = content[..cursor].trim_end();
+        before
+            .rfind(char::is_whitespace)
+            .map(|index| index + before[index..].chars().next().unwrap().len_utf8())
+            .unwrap_or(0)
+    }
+
+    pub fn line_start(content: &str, cursor: usize) -> usize {
+        content[..cursor]
+            .rfind('\n')
+            .map(|index| index + 1)
+            .unwrap_or(0)
+    }
+
+    pub fn line_end(content: &str, cursor: usize) -> usize {
+        content[cursor..]
+            .find('\n')
+            .map(|index| cursor + index)
+            .unwrap_or(content.len())
+    }
+
+    // Position in the line starting at start, at the given display column
+    // or at its end if shorter.
+    fn column_position(content: &str, start: usize, column: usize) -> usize {
+        let end = line_end(content, start);
+        let mut width = 0;
+        for (index, grapheme) in content[start..end].grapheme_indices(true) {
+            width += grapheme.width();
+            if width > column {
+                return start + index;
+            }
+        }
+        end
+    }
+
+    /// Same column on the previous line, None on the first line.
+    pub fn line_up(content: &str, cursor: usize) -> Option<usize> {
+        let start = line_start(content, cursor);
+        if start == 0 {
+            return None;
+        }
+        let column = content[start..cursor].width();
+        Some(column_position(
+            content,
+            line_start(content, start - 1),
+            column,
+        ))
+    }
+
+    /// Same column on the next line, None on the last line.
+    pub fn line_down(content: &str, cursor: usize) -> Option<usize> {
+        let end = line_end(content, cursor);
+        if end == content.len() {
+            return None;
+        }
+        let column = content[line_start(content, cursor)..cursor].width();
+        Some(column_position(content, end + 1, column))
+    }
+
+    /// First byte of the content to show so the cursor fits in the width.
+    pub fn visible_start(content: &str, cursor: usize, width: usize) -> usize {
+        // Keep a column for the cursor when it is at the end.
+        let cursor_width = content[cursor..]
+            .graphemes(true)
+            .next()
+            .map(|grapheme| grapheme.width())
+            .unwrap_or(1);
+        content[..cursor]
+            .grapheme_indices(true)
+            .map(|(index, _)| index)
+            .find(|index| content[*index..cursor].width() + cursor_width <= width)
+            .unwrap_or(cursor)
+    }
+
+    // Killed texts, the most recent last.
+    pub struct KillRing {
+        entries: Vec<String>,
+        max_size: usize,
+        // Entry last yanked.
+        position: usize,
+    }
+
+    impl KillRing {
+        pub fn new(max_size: usize) -> Self {
+            KillRing {
+                entries: Vec::new(),
+                max_size,
+                position: 0,
+            }
+        }
+
+        pub fn push(&mut self, text: &str) {
+            self.entries.push(text.to_string());
+            if self.entries.len() > self.max_size {
+                self.entries.remove(0);
+            }
+            self.position = self.entries.len() - 1;
+        }
+
+        /// Adds the text to the last entry, for consecutive kills.
+        pub fn append(&mut self, text: &str) {
+            match self.entries.last_mut() {
+                Some(entry) => entry.push_str(text),
+                None => self.push(text),
+            }
+        }
+
+        /// Adds the text in front of the last entry, for consecutive
+        /// backward kills.
+        pub fn prepend(&mut self, text: &str) {
+            match self.entries.last_mut() {
+                Some(entry) => entry.insert_str(0, text),
+                None => self.push(text),
+            }
+        }
+
+        /// Most recently killed text.
+        pub fn yank(&mut self) -> Option<&str> {
+            self.position = self.entries.len().checked_sub(1)?;
+            Some(&self.entries[self.position])
+        }
+
+        /// Text killed before the one last yanked, cycling back to the
+        /// most recent one.
+        pub fn yank_pop(&mut self) -> Option<&str> {
+            if self.entries.is_empty() {
+                return None;
+            }
+            self.position = match self.position {
+                0 => self.entries.len() - 1,
+                position => position - 1,
+            };
+            Some(&self.entries[self.position])
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_grapheme_motions() {
+            let content = "e\u{301}日x";
+            assert_eq!(next_grapheme(content, 0), 3);
+            assert_eq!(next_grapheme(content, 3), 6);
+            assert_eq!(next_grapheme(content, 7), 7);
+            assert_eq!(previous_grapheme(content, 6), 3);
+            assert_eq!(previous_grapheme(content, 3), 0);
+            assert_eq!(previous_grapheme(content, 0), 0);
+        }
+
+        #[test]
+        fn test_word_motions() {
+            let content = "cat héllo/txt  wörld";
+            assert_eq!(next_word_end(content, 0), 3);
+            assert_eq!(next_word_end(content, 3), 10);
+            assert_eq!(next_word_end(content, 10), 14);
+            assert_eq!(next_word_end(content, 22), 22);
+            assert_eq!(previous_word_start(content, 22), 16);
+            assert_eq!(previous_word_start(content, 16), 11);
+            assert_eq!(previous_word_start(content, 6), 4);
+            assert_eq!(previous_word_start(content, 2), 0);
+            assert_eq!(previous_blank_word_start(content, 14), 4);
+            assert_eq!(previous_blank_word_start(content, 16), 4);
+            assert_eq!(previous_blank_word_start("ls", 2), 0);
+        }
+
+        #[test]
+        fn test_line_motions() {
+            let content = "for i in 1\n日本\ndone";
+            assert_eq!(line_start(content, 14), 11);
+            assert_eq!(line_end(content, 14), 17);
+            assert_eq!(line_up(content, 4), None);
+            assert_eq!(line_down(content, 4), Some(17));
+            // Wide characters take two columns.
+            assert_eq!(line_down(content, 2), Some(14));
+            assert_eq!(line_up(content, 14), Some(2));
+            assert_eq!(line_down(content, 17), Some(22));
+            assert_eq!(line_down(content, 22), None);
+        }
+
+        #[test]
+        fn test_visible_start() {
+            assert_eq!(visible_start("echo", 4, 10), 0);
+            assert_eq!(visible_start("echo", 4, 4), 1);
+            // Wide characters take two columns.
+            assert_eq!(visible_start("日本語", 9, 5), 3);
+            assert_eq!(visible_start("日本語", 3, 3), 3);
+            assert_eq!(visible_start("日本語", 3, 4), 0);
+        }
+
+        #[test]
+        fn test_kill_ring() {
+            let mut ring = KillRing::new(2);
+            assert_eq!(ring.yank(), None);
+            ring.push("a");
+            ring.append("b");
+            ring.prepend("c");
+            assert_eq!(ring.yank(), Some("cab"));
+            ring.push("d");
+            ring.push("e");
+            assert_eq!(ring.yank(), Some("e"));
+            assert_eq!(ring.yank_pop(), Some("d"));
+            assert_eq!(ring.yank_pop(), Some("e"));
+        }
+    }
+}
@@ -0,0 +1,143 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod executables {
+    use crate::userenv::userenv;
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::sync::{Mutex, MutexGuard};
+
+    // Programs found on the PATH, read again when the PATH changes.
+    struct Cache {
+        path: String,
+        names: BTreeSet<String>,
+    }
+
+    static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+    /// Tells whether a program of this name is on the PATH.
+    pub fn contains(name: &str) -> bool {
+        names().as_ref().unwrap().names.contains(name)
+    }
+
+    /// Programs of the PATH starting with the prefix, in order.
+    pub fn with_prefix(prefix: &str) -> Vec<String> {
+        names()
+            .as_ref()
+            .unwrap()
+            .names
+            .range(prefix.to_string()..)
+            .take_while(|name| name.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Reads the PATH again on next use, programs may have been installed.
+    pub fn invalidate() {
+        *lock() = None;
+    }
+
+    pub fn is_executable(path: &Path) -> bool {
+        match fs::metadata(path) {
+            Ok(metadata) => metadata.is_file() && metadata.permissions().mode() & 0o111 != 0,
+            Err(_) => false,
+        }
+    }
+
+    fn lock() -> MutexGuard<'static, Option<Cache>> {
+        CACHE.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    // Cache of the current PATH, the programs being read when missing.
+    fn names() -> MutexGuard<'static, Option<Cache>> {
+        let path = userenv::path();
+        let mut cache = lock();
+        let stale = match cache.as_ref() {
+            Some(cache) => cache.path != path,
+            None => true,
+        };
+        if stale {
+            log::debug!("Reading the programs of {}", path);
+            *cache = Some(Cache {
+                names: read_names(&path),
+                path,
+            });
+        }
+        cache
+    }
+
+    fn read_names(path: &str) -> BTreeSet<String> {
+        path.split(':')
+            .filter(|dir| !dir.is_empty())
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|entry| is_executable(&entry.path()))
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::{env, process};
+
+        #[test]
+        fn test_read_names() {
+            let dir = env::temp_dir().join(format!("manette-test-executables-{}", process::id()));
+            fs::create_dir_all(dir.join("bin")).unwrap();
+            fs::create_dir_all(dir.join("sbin")).unwrap();
+            for (name, mode) in [
+                ("bin/make", 0o755),
+                ("bin/notes", 0o644),
+                ("sbin/mount", 0o700),
+            ] {
+                fs::write(dir.join(name), "").unwrap();
+                fs::set_permissions(dir.join(name), fs::Permissions::from_mode(mode)).unwrap();
+            }
+            let path = format!(
+                "{}::{}",
+                dir.join("bin").display(),
+                dir.join("sbin").display()
+            );
+            let names: Vec<String> = read_names(&path).into_iter().collect();
+            assert_eq!(names, ["make", "mount"]);
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
@@ -36,41 +36,53 @@ knowledge of the CeCILL license and that you accept its terms.
 
 pub mod open {
     use cursive::Cursive;
-    use std::process::Command;
+    use std::path::Path;
 
     use crate::command::run;
+    use crate::file::filekind;
+    use crate::highlight::highlight;
+    use crate::opener::opener::{OpenMode, Openers};
     use crate::ui::update;
 
+    /// Opens the file with the first opener matching its name or its MIME
+    /// type.
     pub fn open_file(s: &mut Cursive, filename: &str) {
-        let output = Command::new("file")
-            .arg("-b")
-            .arg("--mime-type")
-            .arg(filename)
-            .output();
-        match output {
-            Ok(output) => {
-                let mut output = String::from_utf8(output.stdout).unwrap();
-                if output.ends_with('\n') {
-                    output.pop();
-                    if output.ends_with('\r') {
-                        output.pop();
-                    }
-                }
-                log::debug!("file output is {}", output);
-                match output.as_str() {
-                    "text/plain" => {
-                        log::debug!("File is text");
-                        run::run_command(format!("cat {}", filename).as_str(), s);
-                    }
-                    _ => {
-                        log::debug!("File is something else");
-                    }
-                };
-            }
+        let kind = match filekind::detect(Path::new(filename)) {
+            Ok(kind) => kind,
             Err(error) => {
-                log::error!("Failed to change dir {:?}", error);
-                update::show_error(s, format!("Failed to change dir {:?}", error))
+                log::error!("Cannot detect the type of {}: {:?}", filename, error);
+                update::show_error(s, format!("Cannot open {}: {}", filename, error));
+                return;
+            }
+        };
+        let mime_type = &kind.mime_type;
+        log::debug!("{} is {} ({:?})", filename, mime_type, kind.category);
+        let openers = Openers::load_default();
+        let opener = match openers.find(filename, &kind) {
+            Some(opener) => opener,
+            None => {
+                update::show_error(s, format!("No opener for {} ({})", filename, mime_type));
+                return;
             }
+        };
+        if !highlight::is_command(opener.program()) {
+            update::show_error(
+                s,
+                format!(
+                    "Cannot open {} ({}): {} is not installed",
+                    filename,
+                    mime_type,
+                    opener.program()
+                ),
+            );
+            return;
+        }
+        let command = opener.command(filename);
+        log::debug!("Opening {} with {}", filename, command);
+        match opener.mode {
+            OpenMode::Detached => run::run_detached_command("/bin/sh", vec!["-c", &command], s),
+            OpenMode::Pty => run::run_terminal_command(&command, s),
+            OpenMode::Inline => run::run_command(&command, s),
         }
     }
 }
@@ -114,3 +126,281 @@ pub mod filetype {
         Unknown,
     }
 }
+
+pub mod filekind {
+    use std::fs::{self, File};
+    use std::io::{self, Read};
+    use std::os::unix::fs::FileTypeExt;
+    use std::path::Path;
+    use std::str;
+
+    // Bytes read to detect the type of a file.
+    const SNIFF_BYTES: u64 = 8192;
+
+    // Bytes found at an offset of files of a type.
+    const MAGIC: [(usize, &[u8], &str); 24] = [
+        (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+        (0, b"\xff\xd8\xff", "image/jpeg"),
+        (0, b"GIF87a", "image/gif"),
+        (0, b"GIF89a", "image/gif"),
+        (8, b"WEBP", "image/webp"),
+        (0, b"II*\0", "image/tiff"),
+        (0, b"MM\0*", "image/tiff"),
+        (0, b"%PDF-", "application/pdf"),
+        (0, b"PK\x03\x04", "application/zip"),
+        (0, b"\x1f\x8b", "application/gzip"),
+        (0, b"BZh", "application/x-bzip2"),
+        (0, b"\xfd7zXZ\0", "application/x-xz"),
+        (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (0, b"Rar!\x1a\x07", "application/vnd.rar"),
+        (257, b"ustar", "application/x-tar"),
+        (0, b"\x7fELF", "application/x-executable"),
+        (0, b"\0asm", "application/wasm"),
+        (0, b"SQLite format 3\0", "application/vnd.sqlite3"),
+        (0, b"OggS", "audio/ogg"),
+        (0, b"fLaC", "audio/flac"),
+        (0, b"ID3", "audio/mpeg"),
+        (4, b"ftyp", "video/mp4"),
+        (0, b"\x1a\x45\xdf\xa3", "video/x-matroska"),
+    ];
+
+    const ARCHIVE_TYPES: [&str; 12] = [
+        "application/zip",
+        "application/gzip",
+        "application/x-bzip2",
+        "application/x-xz",
+        "application/zstd",
+        "application/x-7z-compressed",
+        "application/vnd.rar",
+        "application/x-rar-compressed",
+        "application/x-tar",
+        "application/x-compress",
+        "application/java-archive",
+        "application/vnd.debian.binary-package",
+    ];
+
+    const EXECUTABLE_TYPES: [&str; 4] = [
+        "application/x-executable",
+        "application/x-sharedlib",
+        "application/vnd.microsoft.portable-executable",
+        "application/wasm",
+    ];
+
+    const DOCUMENT_PREFIXES: [&str; 6] = [
+        "application/pdf",
+        "application/postscript",
+        "application/epub",
+        "application/msword",
+        "application/vnd.openxmlformats-officedocument",
+        "application/vnd.oasis.opendocument",
+    ];
+
+    /// What a file holds, telling how to show or open it.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Category {
+        Directory,
+        Text,
+        Image,
+        Audio,
+        Video,
+        Archive,
+        Document,
+        Executable,
+        Binary,
+        Empty,
+        // Devices, pipes and sockets.
+        Special,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct FileKind {
+        pub mime_type: String,
+        pub category: Category,
+    }
+
+    impl FileKind {
+        fn new(mime_type: &str, category: Category) -> Self {
+            FileKind {
+                mime_type: mime_type.to_string(),
+                category,
+            }
+        }
+
+        pub fn is_text(&self) -> bool {
+            self.category == Category::Text
+        }
+    }
+
+    /// Kind of the file, detected from its content and its name. Only the
+    /// start of the file is read.
+    pub fn detect(path: &Path) -> io::Result<FileKind> {
+        let metadata = fs::metadata(path)?;
+        let file_type = metadata.file_type();
+        if file_type.is_dir() {
+            return Ok(FileKind::new("inode/directory", Category::Directory));
+        }
+        // Reading devices or pipes could block or never end.
+        let special_type = match file_type {
+            _ if file_type.is_char_device() => Some("inode/chardevice"),
+            _ if file_type.is_block_device() => Some("inode/blockdevice"),
+            _ if file_type.is_fifo() => Some("inode/fifo"),
+            _ if file_type.is_socket() => Some("inode/socket"),
+            _ => None,
+        };
+        if let Some(mime_type) = special_type {
+            return Ok(FileKind::new(mime_type, Category::Special));
+        }
+        let mut content = Vec::new();
+        File::open(path)?
+            .take(SNIFF_BYTES)
+            .read_to_end(&mut content)?;
+        Ok(from_content(path, &content))
+    }
+
+    /// Kind of a file from the start of its content, its magic bytes
+    /// prevailing over its extension.
+    pub fn from_content(path: &Path, content: &[u8]) -> FileKind {
+        if content.is_empty() {
+            return FileKind::new("inode/x-empty", Category::Empty);
+        }
+        let magic = MAGIC
+            .iter()
+            .find(|(offset, bytes, _)| content.get(*offset..offset + bytes.len()) == Some(*bytes));
+        if let Some((_, _, mime_type)) = magic {
+            return FileKind::new(mime_type, category(mime_type));
+        }
+        let guessed = from_name(path);
+        match decode_text(content) {
+            // Text files keep the type of their extension, such as
+            // application/json or image/svg+xml, unless it is a binary format.
+            Some(_) => match guessed {
+                Some(kind)
+                    if matches!(kind.category, Category::Text | Category::Binary)
+                        || kind.mime_type.ends_with("+xml") =>
+                {
+                    FileKind::new(&kind.mime_type, Category::Text)
+                }
+                _ => FileKind::new("text/plain", Category::Text),
+            },
+            None => match guessed {
+                Some(kind) if kind.category != Category::Text => kind,
+                _ => FileKind::new("application/octet-stream", Category::Binary),
+            },
+        }
+    }
+
+    /// Kind of a file guessed from its extension, without reading it.
+    pub fn from_name(path: &Path) -> Option<FileKind> {
+        let mime_type = mime_guess::from_path(path).first()?;
+        let mime_type = mime_type.essence_str();
+        Some(FileKind::new(mime_type, category(mime_type)))
+    }
+
+    /// Content as text, None if it is binary. The content may end in the
+    /// middle of a character, having been cut.
+    pub fn decode_text(content: &[u8]) -> Option<&str> {
+        if content.contains(&0) {
+            return None;
+        }
+        match str::from_utf8(content) {
+            Ok(text) => Some(text),
+            Err(error) if error.error_len().is_none() => {
+                str::from_utf8(&content[..error.valid_up_to()]).ok()
+            }
+            Err(_) => None,
+        }
+    }
+
+    fn category(mime_type: &str) -> Category {
+        let (top_level, _) = mime_type.split_once('/').unwrap_or((mime_type, ""));
+        match top_level {
+            "text" => Category::Text,
+            "image" => Category::Image,
+            "audio" => Category::Audio,
+            "video" => Category::Video,
+            _ if ARCHIVE_TYPES.contains(&mime_type) => Category::Archive,
+            _ if EXECUTABLE_TYPES.contains(&mime_type) => Category::Executable,
+            _ if DOCUMENT_PREFIXES
+                .iter()
+                .any(|prefix| mime_type.starts_with(prefix)) =>
+            {
+                Category::Document
+            }
+            _ => Category::Binary,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_decode_text() {
+            assert_eq!(decode_text(b"hello\n"), Some("hello\n"));
+            // Cut in the middle of é.
+            assert_eq!(decode_text(&"café".as_bytes()[..4]), Some("caf"));
+            assert_eq!(decode_text(b"a\0b"), None);
+            assert_eq!(decode_text(b"\xff\xfeab"), None);
+        }
+
+        #[test]
+        fn test_from_content() {
+            let kind = |name: &str, content: &[u8]| from_content(Path::new(name), content);
+            assert_eq!(
+                kind("image", b"\x89PNG\r\n\x1a\n\0\0"),
+                FileKind::new("image/png", Category::Image)
+            );
+            // The content prevails over the extension.
+            assert_eq!(
+                kind("a.txt", b"\x7fELF\x02\x01").category,
+                Category::Executable
+            );
+            assert_eq!(kind("main.rs", b"fn main() {}\n").mime_type, "text/x-rust");
+            assert_eq!(
+                kind("data.json", b"{}\n"),
+                FileKind::new("application/json", Category::Text)
+            );
+            assert_eq!(kind("README", b"hello\n").mime_type, "text/plain");
+            assert_eq!(kind("a.png", b"not an image\n").mime_type, "text/plain");
+            assert_eq!(kind("icon.svg", b"<svg/>\n").mime_type, "image/svg+xml");
+            assert_eq!(kind("notes.txt", b"\0\x01\x02").category, Category::Binary);
+            assert_eq!(
+                kind("data", b"\0\x01\x02").mime_type,
+                "application/octet-stream"
+            );
+            assert_eq!(kind("a.txt", b"").category, Category::Empty);
+        }
+
+        #[test]
+        fn test_detect() {
+            assert_eq!(
+                detect(Path::new("src")).unwrap().category,
+                Category::Directory
+            );
+            assert_eq!(
+                detect(Path::new("/dev/null")).unwrap().category,
+                Category::Special
+            );
+            assert!(detect(Path::new("Cargo.toml")).unwrap().is_text());
+            assert!(detect(Path::new("does-not-exist")).is_err());
+        }
+
+        #[test]
+        fn test_from_name() {
+            assert_eq!(
+                from_name(Path::new("a.tar.gz")).unwrap().category,
+                Category::Archive
+            );
+            assert_eq!(
+                from_name(Path::new("a.jpg")).unwrap().category,
+                Category::Image
+            );
+            assert_eq!(
+                from_name(Path::new("a.pdf")).unwrap().category,
+                Category::Document
+            );
+            assert!(from_name(Path::new("Makefile")).is_none());
+        }
+    }
+}
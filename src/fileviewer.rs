@@ -0,0 +1,500 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod fileviewer {
+    use std::fs::File;
+    use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // Bytes read from a line, the rest of longer lines being cut so lines
+    // without end, like in minified files, do not fill the memory.
+    pub const MAX_LINE_BYTES: u64 = 64 * 1024;
+
+    /// Lines of a file, read as they are needed so large files open
+    /// instantly. Only the offsets of the lines seen are kept in memory.
+    pub struct LazyLines<R> {
+        reader: R,
+        // Offset of the start of each line found so far.
+        offsets: Vec<u64>,
+        // Offset of the end of the last line found.
+        end: u64,
+        // Offset the reader is at, to avoid seeking when reading on.
+        position: u64,
+        complete: bool,
+    }
+
+    impl LazyLines<BufReader<File>> {
+        pub fn open(path: &Path) -> io::Result<Self> {
+            Ok(LazyLines::new(BufReader::new(File::open(path)?)))
+        }
+    }
+
+    impl<R: BufRead + Seek> LazyLines<R> {
+        pub fn new(reader: R) -> Self {
+            LazyLines {
+                reader,
+                offsets: Vec::new(),
+                end: 0,
+                position: 0,
+                complete: false,
+            }
+        }
+
+        /// Number of lines found so far, which is the number of lines of
+        /// the file once complete.
+        pub fn len(&self) -> usize {
+            self.offsets.len()
+        }
+
+        /// Tells whether the whole file was read.
+        pub fn is_complete(&self) -> bool {
+            self.complete
+        }
+
+        /// Reads the file until it has found this number of lines, or
+        /// reached its end.
+        pub fn index_to(&mut self, count: usize) {
+            if let Err(error) = self.read_offsets(count) {
+                log::error!("Cannot read lines: {:?}", error);
+                self.complete = true;
+            }
+        }
+
+        pub fn index_all(&mut self) {
+            self.index_to(usize::MAX);
+        }
+
+        /// Line at this index, without its end of line and cut after
+        /// MAX_LINE_BYTES. Invalid UTF-8 is replaced.
+        pub fn line(&mut self, index: usize) -> Option<String> {
+            self.index_to(index.saturating_add(1));
+            let offset = *self.offsets.get(index)?;
+            let mut buffer = Vec::new();
+            if let Err(error) = self.read_line_at(offset, &mut buffer) {
+                log::error!("Cannot read line {}: {:?}", index + 1, error);
+                return None;
+            }
+            Some(line_text(&buffer))
+        }
+
+        /// Offset of the start of the line at this index, or of the
+        /// indexed end for the line following the last one found.
+        pub fn line_offset(&mut self, index: usize) -> u64 {
+            self.index_to(index.saturating_add(1));
+            self.offsets.get(index).copied().unwrap_or(self.end)
+        }
+
+        /// Tells whether the line is longer than what is read of it.
+        pub fn is_cut(&self, index: usize) -> bool {
+            let start = match self.offsets.get(index) {
+                Some(start) => *start,
+                None => return false,
+            };
+            let end = self.offsets.get(index + 1).copied().unwrap_or(self.end);
+            // The end of line is not counted.
+            end - start > MAX_LINE_BYTES + 1
+        }
+
+        /// Offset up to which the lines were found.
+        pub fn indexed_end(&self) -> u64 {
+            self.end
+        }
+
+        /// Adds the offsets of the lines found from the indexed end up to
+        /// the end offset, reading the file in another thread. Complete
+        /// when the end of the file was reached.
+        pub fn add_offsets(&mut self, offsets: Vec<u64>, end: u64, complete: bool) {
+            if self.complete {
+                return;
+            }
+            let known = self.end;
+            self.offsets
+                .extend(offsets.into_iter().filter(|offset| *offset >= known));
+            self.end = end.max(self.end);
+            self.complete = complete;
+        }
+
+        /// Finds the last line containing the query up to the start line,
+        /// going on from the end of the file when reaching its start.
+        pub fn find_backward(&mut self, query: &str, start: usize) -> Option<usize> {
+            let matcher = Matcher::new(query);
+            self.index_all();
+            let start = start.min(self.len().checked_sub(1)?);
+            (0..=start)
+                .rev()
+                .chain((start + 1..self.len()).rev())
+                .find(|index| self.line_matches(*index, &matcher))
+        }
+
+        fn line_matches(&mut self, index: usize, matcher: &Matcher) -> bool {
+            self.line(index)
+                .map(|line| matcher.is_match(&line))
+                .unwrap_or(false)
+        }
+
+        fn read_offsets(&mut self, count: usize) -> io::Result<()> {
+            if self.position != self.end {
+                self.reader.seek(SeekFrom::Start(self.end))?;
+                self.position = self.end;
+            }
+            while self.offsets.len() < count && !self.complete {
+                let read = skip_line(&mut self.reader)?;
+                if read == 0 {
+                    self.complete = true;
+                } else {
+                    self.offsets.push(self.end);
+                    self.end += read;
+                    self.position = self.end;
+                }
+            }
+            Ok(())
+        }
+
+        fn read_line_at(&mut self, offset: u64, buffer: &mut Vec<u8>) -> io::Result<u64> {
+            if self.position != offset {
+                self.reader.seek(SeekFrom::Start(offset))?;
+            }
+            let read = (&mut self.reader)
+                .take(MAX_LINE_BYTES + 1)
+                .read_until(b'\n', buffer)? as u64;
+            self.position = offset + read;
+            Ok(read)
+        }
+    }
+
+    /// Line found by find_forward, with the offsets of the lines read past
+    /// the indexed end, up to the end offset.
+    pub struct Found {
+        pub line: Option<usize>,
+        pub offsets: Vec<u64>,
+        pub end: u64,
+        // Whether the end of the file was reached.
+        pub complete: bool,
+    }
+
+    /// Finds the first line containing the query from the start line, at
+    /// the start offset, going on from the start of the file when reaching
+    /// its end. Used to search large files without blocking the viewer,
+    /// None being returned once cancelled.
+    pub fn find_forward(
+        path: &Path,
+        query: &str,
+        (start, start_offset): (usize, u64),
+        indexed_end: u64,
+        cancelled: &AtomicBool,
+    ) -> io::Result<Option<Found>> {
+        let matcher = Matcher::new(query);
+        let mut reader = BufReader::new(File::open(path)?);
+        reader.seek(SeekFrom::Start(start_offset))?;
+        let mut found = Found {
+            line: None,
+            offsets: Vec::new(),
+            end: start_offset,
+            complete: false,
+        };
+        let mut index = start;
+        let mut buffer = Vec::new();
+        loop {
+            if cancelled.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            buffer.clear();
+            let read = read_line(&mut reader, &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            if found.end >= indexed_end {
+                found.offsets.push(found.end);
+            }
+            found.end += read;
+            if matcher.is_match(&line_text(&buffer)) {
+                found.line = Some(index);
+                return Ok(Some(found));
+            }
+            index += 1;
+        }
+        found.complete = true;
+        // The lines before the start ones were all found already.
+        reader.seek(SeekFrom::Start(0))?;
+        for index in 0..start {
+            if cancelled.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            buffer.clear();
+            if read_line(&mut reader, &mut buffer)? == 0 {
+                break;
+            }
+            if matcher.is_match(&line_text(&buffer)) {
+                found.line = Some(index);
+                break;
+            }
+        }
+        Ok(Some(found))
+    }
+
+    /// Offsets of the lines of the file from the start offset, which is
+    /// the start of a line, and the offset of its end. Used to read large
+    /// files without blocking the viewer.
+    pub fn line_offsets(path: &Path, start: u64) -> io::Result<(Vec<u64>, u64)> {
+        let mut reader = BufReader::new(File::open(path)?);
+        reader.seek(SeekFrom::Start(start))?;
+        let mut offsets = Vec::new();
+        let mut end = start;
+        loop {
+            let read = skip_line(&mut reader)?;
+            if read == 0 {
+                return Ok((offsets, end));
+            }
+            offsets.push(end);
+            end += read;
+        }
+    }
+
+    // Reads the next line, keeping only its first MAX_LINE_BYTES and the
+    // byte after, and returns the number of bytes read.
+    fn read_line<R: BufRead>(reader: &mut R, buffer: &mut Vec<u8>) -> io::Result<u64> {
+        let read = reader.take(MAX_LINE_BYTES + 1).read_until(b'\n', buffer)? as u64;
+        match buffer.last() {
+            Some(b'\n') | None => Ok(read),
+            Some(_) => Ok(read + skip_line(reader)?),
+        }
+    }
+
+    // Text of a line read, without its end of line and cut after
+    // MAX_LINE_BYTES. Invalid UTF-8 is replaced.
+    fn line_text(buffer: &[u8]) -> String {
+        let mut line = &buffer[..buffer.len().min(MAX_LINE_BYTES as usize)];
+        while let Some((b'\n', rest)) | Some((b'\r', rest)) = line.split_last() {
+            line = rest;
+        }
+        String::from_utf8_lossy(line).into_owned()
+    }
+
+    // Reads past the next end of line without keeping the line, returning
+    // the number of bytes read.
+    fn skip_line<R: BufRead>(reader: &mut R) -> io::Result<u64> {
+        let mut read = 0;
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(read);
+            }
+            match buffer.iter().position(|byte| *byte == b'\n') {
+                Some(index) => {
+                    reader.consume(index + 1);
+                    return Ok(read + index as u64 + 1);
+                }
+                None => {
+                    let length = buffer.len();
+                    reader.consume(length);
+                    read += length as u64;
+                }
+            }
+        }
+    }
+
+    // Matches the query ignoring case, unless it contains upper case
+    // letters.
+    struct Matcher {
+        query: String,
+        ignore_case: bool,
+    }
+
+    impl Matcher {
+        fn new(query: &str) -> Self {
+            let ignore_case = !query.chars().any(char::is_uppercase);
+            Matcher {
+                query: match ignore_case {
+                    true => query.to_lowercase(),
+                    false => query.to_string(),
+                },
+                ignore_case,
+            }
+        }
+
+        fn is_match(&self, line: &str) -> bool {
+            match self.ignore_case {
+                true => line.to_lowercase().contains(&self.query),
+                false => line.contains(&self.query),
+            }
+        }
+
+        // Length in bytes of the match at the start of the text.
+        fn match_at(&self, text: &str) -> Option<usize> {
+            let mut chars = text.char_indices();
+            for query_char in self.query.chars() {
+                let (_, ch) = chars.next()?;
+                let equal = match self.ignore_case {
+                    true => ch.to_lowercase().eq(query_char.to_lowercase()),
+                    false => ch == query_char,
+                };
+                if !equal {
+                    return None;
+                }
+            }
+            Some(chars.next().map(|(index, _)| index).unwrap_or(text.len()))
+        }
+    }
+
+    /// Byte ranges of the occurrences of the query in the line, matched
+    /// like when searching.
+    pub fn match_ranges(line: &str, query: &str) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        if query.is_empty() {
+            return ranges;
+        }
+        let matcher = Matcher::new(query);
+        let mut start = 0;
+        while let Some(ch) = line[start..].chars().next() {
+            match matcher.match_at(&line[start..]) {
+                Some(length) => {
+                    ranges.push((start, start + length));
+                    start += length;
+                }
+                None => start += ch.len_utf8(),
+            }
+        }
+        ranges
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::io::Cursor;
+        use std::{env, fs, process};
+
+        fn lines(content: &str) -> LazyLines<Cursor<Vec<u8>>> {
+            LazyLines::new(Cursor::new(content.as_bytes().to_vec()))
+        }
+
+        #[test]
+        fn test_lazy_lines() {
+            let mut lines = lines("first\r\nsecond\n\nlast");
+            assert_eq!(lines.line(1).unwrap(), "second");
+            assert_eq!(lines.len(), 2);
+            assert!(!lines.is_complete());
+            assert_eq!(lines.line(0).unwrap(), "first");
+            assert_eq!(lines.line(2).unwrap(), "");
+            assert_eq!(lines.line(3).unwrap(), "last");
+            assert_eq!(lines.line(4), None);
+            assert!(lines.is_complete());
+            assert_eq!(lines.len(), 4);
+            assert!(self::lines("").line(0).is_none());
+        }
+
+        #[test]
+        fn test_long_lines() {
+            let long = "a".repeat(MAX_LINE_BYTES as usize + 10);
+            let mut lines = lines(&format!("short\n{}\nend\n", long));
+            assert_eq!(lines.line(1).unwrap().len(), MAX_LINE_BYTES as usize);
+            assert!(lines.is_cut(1));
+            assert!(!lines.is_cut(0));
+            assert_eq!(lines.line(2).unwrap(), "end");
+        }
+
+        #[test]
+        fn test_add_offsets() {
+            let content = "one\ntwo\nthree\nfour";
+            let mut lines = lines(content);
+            assert_eq!(lines.line(0).unwrap(), "one");
+            let path = env::temp_dir().join(format!("manette-test-offsets-{}", process::id()));
+            fs::write(&path, content).unwrap();
+            let (offsets, end) = line_offsets(&path, 0).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(offsets, [0, 4, 8, 14]);
+            assert_eq!(end, content.len() as u64);
+            // The lines already found are kept.
+            assert_eq!(lines.line(1).unwrap(), "two");
+            lines.add_offsets(offsets, end, true);
+            assert!(lines.is_complete());
+            assert_eq!(lines.len(), 4);
+            assert_eq!(lines.line(3).unwrap(), "four");
+        }
+
+        #[test]
+        fn test_find_backward() {
+            let mut lines = lines("fn main() {\n    let Value = 1;\n    value += 1;\n}\n");
+            assert_eq!(lines.find_backward("value", 3), Some(2));
+            assert_eq!(lines.find_backward("Value", 1), Some(1));
+            // Searches go on from the end of the file.
+            assert_eq!(lines.find_backward("value", 0), Some(2));
+            assert_eq!(lines.find_backward("missing", 3), None);
+        }
+
+        #[test]
+        fn test_find_forward() {
+            let content = "fn main() {\n    let Value = 1;\n    value += 1;\n}\n";
+            let path = env::temp_dir().join(format!("manette-test-find-{}", process::id()));
+            fs::write(&path, content).unwrap();
+            let cancelled = AtomicBool::new(false);
+            let find = |query, start, indexed_end| {
+                find_forward(&path, query, start, indexed_end, &cancelled)
+                    .unwrap()
+                    .unwrap()
+            };
+            let found = find("value", (0, 0), 0);
+            assert_eq!(found.line, Some(1));
+            assert_eq!(found.offsets, [0, 12]);
+            assert_eq!(found.end, 31);
+            assert!(!found.complete);
+            // Only the lines past the indexed end are returned.
+            let found = find("value", (2, 31), 31);
+            assert_eq!((found.line, found.offsets), (Some(2), vec![31]));
+            // Searches go on from the start of the file.
+            let found = find("Value", (2, 31), 31);
+            assert_eq!(found.line, Some(1));
+            assert_eq!((found.offsets, found.end), (vec![31, 47], 49));
+            assert!(found.complete);
+            assert_eq!(find("missing", (1, 12), 49).line, None);
+            cancelled.store(true, Ordering::Relaxed);
+            assert!(find_forward(&path, "value", (0, 0), 0, &cancelled)
+                .unwrap()
+                .is_none());
+            fs::remove_file(&path).unwrap();
+        }
+
+        #[test]
+        fn test_match_ranges() {
+            assert_eq!(match_ranges("Value value", "value"), [(0, 5), (6, 11)]);
+            assert_eq!(match_ranges("Value value", "Value"), [(0, 5)]);
+            assert_eq!(match_ranges("été", "É"), []);
+            assert_eq!(match_ranges("Été", "é"), [(0, 2), (3, 5)]);
+            assert_eq!(match_ranges("aaa", "aa"), [(0, 2)]);
+            assert!(match_ranges("abc", "").is_empty());
+        }
+    }
+}
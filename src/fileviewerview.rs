@@ -0,0 +1,696 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+use crate::command::run;
+use crate::fileviewer::fileviewer::{self, Found, LazyLines};
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::{BaseColor, Color, ColorStyle, Effect, Style};
+use cursive::view::View;
+use cursive::{Printer, Vec2};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{self, FontStyle, Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use unicode_width::UnicodeWidthChar;
+
+// Lines after this one are not highlighted, as highlighting a line needs
+// to parse all the lines before it.
+const HIGHLIGHT_LINES: usize = 5_000;
+// Longer lines are not highlighted.
+const MAX_HIGHLIGHTED_LINE_BYTES: usize = 4096;
+const TAB_WIDTH: usize = 4;
+// Columns scrolled by Left and Right when lines are not wrapped.
+const HORIZONTAL_STEP: usize = 8;
+// Themes for views with a light background and for other backgrounds,
+// like the terminal one which cannot be known.
+const LIGHT_THEME: &str = "InspiredGitHub";
+const DARK_THEME: &str = "base16-ocean.dark";
+
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+
+fn syntaxes() -> &'static SyntaxSet {
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+// Theme readable on the background of the views.
+fn theme(background: Color) -> &'static Theme {
+    let name = match is_light(background) {
+        true => LIGHT_THEME,
+        false => DARK_THEME,
+    };
+    &THEMES.get_or_init(ThemeSet::load_defaults).themes[name]
+}
+
+fn is_light(color: Color) -> bool {
+    match color {
+        Color::Dark(BaseColor::White) | Color::Light(BaseColor::White) => true,
+        Color::Light(BaseColor::Yellow) | Color::Light(BaseColor::Cyan) => true,
+        Color::Rgb(red, green, blue) => {
+            299 * red as u32 + 587 * green as u32 + 114 * blue as u32 > 128_000
+        }
+        _ => false,
+    }
+}
+
+// Text typed in the status line.
+enum Prompt {
+    Search(String),
+    Line(String),
+}
+
+// Action waiting for the lines of the whole file, which are found in
+// another thread.
+enum Pending {
+    End,
+    SearchBackward,
+    Line(usize),
+}
+
+// Row of the screen, the line number being only shown on the first row
+// of a wrapped line.
+struct Row {
+    number: Option<usize>,
+    spans: Vec<(Style, String)>,
+}
+
+// Viewer for text files, highlighting their syntax.
+pub struct FileViewer {
+    path: String,
+    lines: LazyLines<BufReader<File>>,
+    // None when the file type has no syntax.
+    highlighter: Option<HighlightLines<'static>>,
+    // Highlighted lines, from the first one.
+    highlighted: Vec<Vec<(Style, String)>>,
+    // First line shown.
+    top: usize,
+    // Columns skipped when lines are not wrapped.
+    left: usize,
+    wrap: bool,
+    prompt: Option<Prompt>,
+    query: Option<String>,
+    // Line of the last match found.
+    matched: Option<usize>,
+    message: String,
+    // Set while the lines of the file are being found.
+    pending: Option<Pending>,
+    // Set while searching forward in another thread, to cancel it.
+    searching: Option<Arc<AtomicBool>>,
+    size: Vec2,
+    rows: Vec<Row>,
+}
+
+impl FileViewer {
+    /// Opens the file, highlighting it with colours readable on the
+    /// background of the views.
+    pub fn open(path: &str, background: Color) -> io::Result<Self> {
+        let lines = LazyLines::open(Path::new(path))?;
+        let syntax_set = syntaxes();
+        let highlighter = match syntax_set.find_syntax_for_file(path) {
+            Ok(Some(syntax)) if syntax.name != "Plain Text" => {
+                log::debug!("Highlighting {} as {}", path, syntax.name);
+                Some(HighlightLines::new(syntax, theme(background)))
+            }
+            _ => None,
+        };
+        Ok(FileViewer {
+            path: path.to_string(),
+            lines,
+            highlighter,
+            highlighted: Vec::new(),
+            top: 0,
+            left: 0,
+            wrap: false,
+            prompt: None,
+            query: None,
+            matched: None,
+            message: String::new(),
+            pending: None,
+            searching: None,
+            size: Vec2::zero(),
+            rows: Vec::new(),
+        })
+    }
+
+    // Rows available for the lines, the last one being the status line.
+    fn page_rows(&self) -> usize {
+        self.size.y.saturating_sub(1).max(1)
+    }
+
+    fn scroll_to(&mut self, line: usize) {
+        let rows = self.page_rows();
+        self.lines.index_to(line.saturating_add(rows));
+        // The last page is kept full once the end of the file is known.
+        let last = match self.lines.is_complete() {
+            true => self.lines.len().saturating_sub(rows),
+            false => self.lines.len().saturating_sub(1),
+        };
+        self.top = line.min(last);
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        self.scroll_to(self.top.saturating_add(count));
+    }
+
+    fn scroll_up(&mut self, count: usize) {
+        self.scroll_to(self.top.saturating_sub(count));
+    }
+
+    fn scroll_to_end(&mut self) {
+        self.scroll_to(self.lines.len());
+    }
+
+    // Runs the action once the lines of the whole file are found,
+    // reading the file in another thread when it was not read yet.
+    fn when_indexed(&mut self, action: Pending) -> EventResult {
+        if self.lines.is_complete() {
+            self.run_pending(action);
+            return EventResult::Consumed(None);
+        }
+        let started = self.pending.is_some();
+        self.pending = Some(action);
+        self.message = "Reading the file...".to_string();
+        if started {
+            return EventResult::Consumed(None);
+        }
+        let path = self.path.clone();
+        let start = self.lines.indexed_end();
+        EventResult::with_cb(move |s| {
+            let sink = s.cb_sink().clone();
+            let path = path.clone();
+            thread::spawn(move || {
+                let offsets = fileviewer::line_offsets(Path::new(&path), start);
+                let sent = sink.send(Box::new(move |s| {
+                    s.call_on_name("file_viewer", |viewer: &mut FileViewer| {
+                        viewer.indexed(&path, offsets)
+                    });
+                }));
+                if let Err(error) = sent {
+                    log::error!("Cannot send the lines found: {:?}", error);
+                }
+            });
+        })
+    }
+
+    fn indexed(&mut self, path: &str, offsets: io::Result<(Vec<u64>, u64)>) {
+        // Another file may be viewed by now.
+        if path != self.path {
+            return;
+        }
+        self.message.clear();
+        match offsets {
+            Ok((offsets, end)) => self.lines.add_offsets(offsets, end, true),
+            Err(error) => {
+                log::error!("Cannot read the lines of {}: {:?}", self.path, error);
+                self.message = format!("Cannot read the file: {}", error);
+                self.pending = None;
+                return;
+            }
+        }
+        if let Some(action) = self.pending.take() {
+            self.run_pending(action);
+        }
+    }
+
+    fn run_pending(&mut self, action: Pending) {
+        match action {
+            Pending::End => self.scroll_to_end(),
+            Pending::SearchBackward => self.search_backward(),
+            Pending::Line(line) => self.go_to_line(line),
+        }
+    }
+
+    // Searches forward from the last match or the top line, reading the
+    // file in another thread as the match may be far in the file.
+    fn search_forward(&mut self, skip_current: bool) -> EventResult {
+        let query = match &self.query {
+            Some(query) => query.clone(),
+            None => return EventResult::Consumed(None),
+        };
+        let current = self.matched.unwrap_or(self.top);
+        let start = match skip_current {
+            true => current + 1,
+            false => current,
+        };
+        let start = (start, self.lines.line_offset(start));
+        let indexed_end = self.lines.indexed_end();
+        self.cancel_search();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.searching = Some(cancelled.clone());
+        let path = self.path.clone();
+        EventResult::with_cb(move |s| {
+            let sink = s.cb_sink().clone();
+            let path = path.clone();
+            let query = query.clone();
+            let cancelled = cancelled.clone();
+            thread::spawn(move || {
+                let found = fileviewer::find_forward(
+                    Path::new(&path),
+                    &query,
+                    start,
+                    indexed_end,
+                    &cancelled,
+                );
+                let found = match found {
+                    Ok(Some(found)) => Ok(found),
+                    Ok(None) => return,
+                    Err(error) => Err(error),
+                };
+                let sent = sink.send(Box::new(move |s| {
+                    s.call_on_name("file_viewer", |viewer: &mut FileViewer| {
+                        viewer.found(&cancelled, &query, found)
+                    });
+                }));
+                if let Err(error) = sent {
+                    log::error!("Cannot send the search result: {:?}", error);
+                }
+            });
+        })
+    }
+
+    fn found(&mut self, search: &Arc<AtomicBool>, query: &str, found: io::Result<Found>) {
+        // Another search may have been started since, or another file
+        // viewed.
+        match &self.searching {
+            Some(searching) if Arc::ptr_eq(searching, search) => self.searching = None,
+            _ => return,
+        }
+        match found {
+            Ok(found) => {
+                self.lines
+                    .add_offsets(found.offsets, found.end, found.complete);
+                self.show_match(query, found.line);
+            }
+            Err(error) => {
+                log::error!("Cannot search {}: {:?}", self.path, error);
+                self.message = format!("Cannot read the file: {}", error);
+            }
+        }
+    }
+
+    fn cancel_search(&mut self) {
+        if let Some(cancelled) = self.searching.take() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn search_backward(&mut self) {
+        let query = match &self.query {
+            Some(query) => query.clone(),
+            None => return,
+        };
+        let start = match self.matched.unwrap_or(self.top) {
+            0 => usize::MAX,
+            current => current - 1,
+        };
+        let line = self.lines.find_backward(&query, start);
+        self.show_match(&query, line);
+    }
+
+    fn show_match(&mut self, query: &str, line: Option<usize>) {
+        match line {
+            Some(line) => {
+                self.matched = Some(line);
+                let rows = self.page_rows();
+                if line < self.top || line >= self.top + rows {
+                    self.scroll_to(line.saturating_sub(rows / 3));
+                }
+                self.message = format!("Match at line {}", line + 1);
+            }
+            None => self.message = format!("Pattern not found: {}", query),
+        }
+    }
+
+    fn go_to_line(&mut self, line: usize) {
+        self.scroll_to(line - 1);
+        if self.top + 1 < line && self.lines.is_complete() {
+            self.message = format!("The file has {} lines", self.lines.len());
+        }
+    }
+
+    fn close(&mut self) -> EventResult {
+        EventResult::with_cb(run::close_viewer)
+    }
+
+    fn on_prompt_event(&mut self, event: Event) -> EventResult {
+        let input = match &mut self.prompt {
+            Some(Prompt::Search(input)) | Some(Prompt::Line(input)) => input,
+            None => return EventResult::Ignored,
+        };
+        match event {
+            Event::Char(ch) => input.push(ch),
+            Event::Key(Key::Backspace) if input.pop().is_none() => self.prompt = None,
+            Event::Key(Key::Backspace) => (),
+            Event::Key(Key::Esc) | Event::CtrlChar('c') | Event::CtrlChar('g') => {
+                self.prompt = None
+            }
+            Event::Key(Key::Enter) => match self.prompt.take() {
+                Some(Prompt::Search(query)) if !query.is_empty() => {
+                    self.query = Some(query);
+                    self.matched = None;
+                    return self.search_forward(false);
+                }
+                // An empty search repeats the last one.
+                Some(Prompt::Search(_)) => return self.search_forward(true),
+                Some(Prompt::Line(line)) => match line.parse::<usize>() {
+                    // Lines not found yet may be far in the file.
+                    Ok(line) if line > self.lines.len() => {
+                        return self.when_indexed(Pending::Line(line))
+                    }
+                    Ok(line) if line > 0 => self.go_to_line(line),
+                    _ => self.message = format!("Invalid line number: {}", line),
+                },
+                None => (),
+            },
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+
+    // Highlighted spans of the line, plain when after the lines which
+    // are highlighted.
+    fn spans(&mut self, index: usize, line: &str) -> Vec<(Style, String)> {
+        let highlighter = match &mut self.highlighter {
+            Some(highlighter) if index < HIGHLIGHT_LINES => highlighter,
+            _ => return vec![(Style::none(), line.to_string())],
+        };
+        // Lines are parsed in order, from the last one highlighted.
+        while self.highlighted.len() <= index {
+            let current = self.highlighted.len();
+            let text = match current == index {
+                true => line.to_string(),
+                false => self.lines.line(current).unwrap_or_default(),
+            };
+            // Highlighting long lines, like minified code, is too slow.
+            if text.len() > MAX_HIGHLIGHTED_LINE_BYTES {
+                self.highlighted.push(vec![(Style::none(), text)]);
+                continue;
+            }
+            let spans = match highlighter.highlight_line(&format!("{}\n", text), syntaxes()) {
+                Ok(regions) => regions
+                    .into_iter()
+                    .map(|(style, text)| {
+                        (
+                            convert_style(style),
+                            text.trim_end_matches('\n').to_string(),
+                        )
+                    })
+                    .collect(),
+                Err(error) => {
+                    log::error!("Cannot highlight {}: {:?}", self.path, error);
+                    self.highlighter = None;
+                    return vec![(Style::none(), line.to_string())];
+                }
+            };
+            self.highlighted.push(spans);
+        }
+        self.highlighted[index].clone()
+    }
+
+    fn layout_rows(&mut self) {
+        self.rows.clear();
+        let height = self.page_rows();
+        let width = self.text_width();
+        let mut index = self.top;
+        while self.rows.len() < height {
+            let line = match self.lines.line(index) {
+                Some(line) => line,
+                None => break,
+            };
+            let mut spans = self.spans(index, &line);
+            if let Some(query) = &self.query {
+                spans = mark_matches(spans, &fileviewer::match_ranges(&line, query));
+            }
+            if self.lines.is_cut(index) {
+                spans.push((ColorStyle::secondary().into(), "…".to_string()));
+            }
+            let rows = split_rows(&spans, width, self.wrap, self.left);
+            for (row, spans) in rows.into_iter().enumerate() {
+                self.rows.push(Row {
+                    number: match row {
+                        0 => Some(index + 1),
+                        _ => None,
+                    },
+                    spans,
+                });
+            }
+            index += 1;
+        }
+        self.rows.truncate(height);
+    }
+
+    fn number_width(&self) -> usize {
+        (self.top + self.page_rows()).to_string().len().max(4)
+    }
+
+    // Width left for the text after the line numbers.
+    fn text_width(&self) -> usize {
+        self.size.x.saturating_sub(self.number_width() + 1).max(1)
+    }
+
+    fn status(&self) -> String {
+        match &self.prompt {
+            Some(Prompt::Search(input)) => format!("/{}", input),
+            Some(Prompt::Line(input)) => format!(":{}", input),
+            None => {
+                let total = match self.lines.is_complete() {
+                    true => self.lines.len().to_string(),
+                    false => format!("{}+", self.lines.len()),
+                };
+                let wrap = match self.wrap {
+                    true => " [wrap]",
+                    false => "",
+                };
+                let message = match self.searching {
+                    Some(_) => "Searching...",
+                    None => &self.message,
+                };
+                format!(
+                    "{}  line {}/{}{}  {}",
+                    self.path,
+                    self.top + 1,
+                    total,
+                    wrap,
+                    message
+                )
+            }
+        }
+    }
+}
+
+impl Drop for FileViewer {
+    fn drop(&mut self) {
+        self.cancel_search();
+    }
+}
+
+impl View for FileViewer {
+    fn draw(&self, printer: &Printer) {
+        let number_width = self.number_width();
+        for (y, row) in self.rows.iter().enumerate() {
+            if let Some(number) = row.number {
+                let highlighted = self.matched == Some(number - 1);
+                printer.with_color(ColorStyle::secondary(), |printer| {
+                    printer.with_effect(
+                        match highlighted {
+                            true => Effect::Reverse,
+                            false => Effect::Simple,
+                        },
+                        |printer| {
+                            printer
+                                .print((0, y), &format!("{:>width$}", number, width = number_width))
+                        },
+                    );
+                });
+            }
+            let mut x = number_width + 1;
+            for (style, text) in &row.spans {
+                printer.with_style(*style, |printer| printer.print((x, y), text));
+                x += text_width(text);
+            }
+        }
+        let y = printer.size.y.saturating_sub(1);
+        printer.with_effect(Effect::Reverse, |printer| {
+            printer.print_hline((0, y), printer.size.x, " ");
+            printer.print((0, y), &self.status());
+        });
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        self.size = size;
+        self.layout_rows();
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        constraint
+    }
+
+    fn take_focus(&mut self, _source: cursive::direction::Direction) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        if self.prompt.is_some() {
+            return self.on_prompt_event(event);
+        }
+        self.message.clear();
+        let page = self.page_rows();
+        match event {
+            Event::Char('q') | Event::Key(Key::Esc) => return self.close(),
+            Event::Char('j') | Event::Key(Key::Down) | Event::Key(Key::Enter) => {
+                self.scroll_down(1)
+            }
+            Event::Char('k') | Event::Key(Key::Up) => self.scroll_up(1),
+            Event::Char(' ') | Event::Char('f') | Event::Key(Key::PageDown) => {
+                self.scroll_down(page)
+            }
+            Event::Char('b') | Event::Key(Key::PageUp) => self.scroll_up(page),
+            Event::Char('d') => self.scroll_down(page / 2),
+            Event::Char('u') => self.scroll_up(page / 2),
+            Event::Char('g') | Event::Key(Key::Home) => self.scroll_to(0),
+            Event::Char('G') | Event::Key(Key::End) => return self.when_indexed(Pending::End),
+            Event::Char('h') | Event::Key(Key::Left) if !self.wrap => {
+                self.left = self.left.saturating_sub(HORIZONTAL_STEP)
+            }
+            Event::Char('l') | Event::Key(Key::Right) if !self.wrap => self.left += HORIZONTAL_STEP,
+            Event::Char('w') => {
+                self.wrap = !self.wrap;
+                self.left = 0;
+            }
+            Event::Char('/') => self.prompt = Some(Prompt::Search(String::new())),
+            Event::Char(':') => self.prompt = Some(Prompt::Line(String::new())),
+            Event::Char('n') => return self.search_forward(true),
+            Event::CtrlChar('c') if self.searching.is_some() => self.cancel_search(),
+            // Searching backward reads the file from its end.
+            Event::Char('N') if self.query.is_some() => {
+                return self.when_indexed(Pending::SearchBackward)
+            }
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+}
+
+fn convert_style(style: highlighting::Style) -> Style {
+    let color = style.foreground;
+    let mut converted = Style::from(ColorStyle::front(Color::Rgb(color.r, color.g, color.b)));
+    if style.font_style.contains(FontStyle::BOLD) {
+        converted.effects.insert(Effect::Bold);
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        converted.effects.insert(Effect::Italic);
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        converted.effects.insert(Effect::Underline);
+    }
+    converted
+}
+
+// Reverses the parts of the spans in the byte ranges.
+fn mark_matches(spans: Vec<(Style, String)>, ranges: &[(usize, usize)]) -> Vec<(Style, String)> {
+    if ranges.is_empty() {
+        return spans;
+    }
+    let mut marked: Vec<(Style, String)> = Vec::new();
+    let mut offset = 0;
+    for (style, text) in spans {
+        for (index, ch) in text.char_indices() {
+            let position = offset + index;
+            let mut style = style;
+            if ranges
+                .iter()
+                .any(|(start, end)| (*start..*end).contains(&position))
+            {
+                style.effects.insert(Effect::Reverse);
+            }
+            match marked.last_mut() {
+                Some((last_style, last_text)) if *last_style == style => last_text.push(ch),
+                _ => marked.push((style, ch.to_string())),
+            }
+        }
+        offset += text.len();
+    }
+    marked
+}
+
+// Splits the spans in rows fitting the width, wrapping them or skipping
+// the first columns. Tabs are expanded.
+fn split_rows(
+    spans: &[(Style, String)],
+    width: usize,
+    wrap: bool,
+    left: usize,
+) -> Vec<Vec<(Style, String)>> {
+    let mut rows: Vec<Vec<(Style, String)>> = vec![Vec::new()];
+    let mut column = 0;
+    let mut row_width = 0;
+    for (style, text) in spans {
+        for ch in text.chars() {
+            let (ch, count) = match ch {
+                '\t' => (' ', TAB_WIDTH - column % TAB_WIDTH),
+                ch => (ch, 1),
+            };
+            for _ in 0..count {
+                let ch_width = ch.width().unwrap_or(0);
+                if !wrap && (column < left || column + ch_width > left + width) {
+                    column += ch_width;
+                    continue;
+                }
+                if wrap && row_width + ch_width > width {
+                    rows.push(Vec::new());
+                    row_width = 0;
+                }
+                let row = rows.last_mut().unwrap();
+                match row.last_mut() {
+                    Some((last_style, last_text)) if last_style == style => last_text.push(ch),
+                    _ => row.push((*style, ch.to_string())),
+                }
+                column += ch_width;
+                row_width += ch_width;
+            }
+        }
+    }
+    rows
+}
+
+fn text_width(text: &str) -> usize {
+    text.chars().map(|ch| ch.width().unwrap_or(0)).sum()
+}
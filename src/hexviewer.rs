@@ -0,0 +1,325 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod hexviewer {
+    use std::collections::VecDeque;
+    use std::fs::File;
+    use std::io::{self, Read, Seek, SeekFrom};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // Bytes read at once when showing the file.
+    const PAGE_SIZE: u64 = 64 * 1024;
+    // Pages kept in memory, the least recently read being dropped first.
+    const CACHED_PAGES: usize = 16;
+    // Bytes read at once when searching the file.
+    const SEARCH_CHUNK: u64 = 1024 * 1024;
+
+    /// File read in pages as it is shown, so files of any size can be
+    /// viewed. Only the last pages read are kept in memory.
+    pub struct PagedFile<R> {
+        reader: R,
+        len: u64,
+        // Index and content of the pages read, the most recent last.
+        pages: VecDeque<(u64, Vec<u8>)>,
+    }
+
+    impl PagedFile<File> {
+        pub fn open(path: &Path) -> io::Result<Self> {
+            let file = File::open(path)?;
+            let len = file.metadata()?.len();
+            Ok(PagedFile::new(file, len))
+        }
+    }
+
+    impl<R: Read + Seek> PagedFile<R> {
+        pub fn new(reader: R, len: u64) -> Self {
+            PagedFile {
+                reader,
+                len,
+                pages: VecDeque::new(),
+            }
+        }
+
+        pub fn len(&self) -> u64 {
+            self.len
+        }
+
+        /// Bytes from the offset, fewer than asked at the end of the file.
+        pub fn read(&mut self, offset: u64, count: usize) -> io::Result<Vec<u8>> {
+            let end = self.len.min(offset.saturating_add(count as u64));
+            let mut bytes = Vec::with_capacity(end.saturating_sub(offset) as usize);
+            let mut position = offset;
+            while position < end {
+                let index = position / PAGE_SIZE;
+                let page = self.page(index)?;
+                let start = (position - index * PAGE_SIZE) as usize;
+                if start >= page.len() {
+                    // The file was truncated since it was opened.
+                    break;
+                }
+                let stop = page.len().min((end - index * PAGE_SIZE) as usize);
+                bytes.extend_from_slice(&page[start..stop]);
+                position = index * PAGE_SIZE + stop as u64;
+            }
+            Ok(bytes)
+        }
+
+        fn page(&mut self, index: u64) -> io::Result<&[u8]> {
+            match self.pages.iter().position(|(page, _)| *page == index) {
+                Some(position) => {
+                    let page = self.pages.remove(position).unwrap();
+                    self.pages.push_back(page);
+                }
+                None => {
+                    let content = self.read_at(index * PAGE_SIZE, PAGE_SIZE as usize)?;
+                    if self.pages.len() == CACHED_PAGES {
+                        self.pages.pop_front();
+                    }
+                    self.pages.push_back((index, content));
+                }
+            }
+            Ok(&self.pages.back().unwrap().1)
+        }
+
+        // Reads the bytes without caching them.
+        fn read_at(&mut self, offset: u64, count: usize) -> io::Result<Vec<u8>> {
+            let mut bytes = Vec::with_capacity(count);
+            self.reader.seek(SeekFrom::Start(offset))?;
+            (&mut self.reader)
+                .take(count as u64)
+                .read_to_end(&mut bytes)?;
+            Ok(bytes)
+        }
+
+        /// Offset of the next occurrence of the pattern from the start
+        /// offset included, or of the previous one when searching
+        /// backward. The search wraps around the ends of the file. Meant
+        /// to run in another thread, it stops between chunks once
+        /// cancelled.
+        pub fn find(
+            &mut self,
+            pattern: &[u8],
+            start: u64,
+            forward: bool,
+            cancelled: &AtomicBool,
+        ) -> io::Result<Option<u64>> {
+            if pattern.is_empty() {
+                return Ok(None);
+            }
+            let start = start.min(self.len);
+            match forward {
+                true => match self.find_forward(pattern, start, self.len, cancelled)? {
+                    Some(offset) => Ok(Some(offset)),
+                    None => self.find_forward(pattern, 0, start, cancelled),
+                },
+                false => {
+                    let end = start.saturating_add(1).min(self.len);
+                    match self.find_backward(pattern, 0, end, cancelled)? {
+                        Some(offset) => Ok(Some(offset)),
+                        None => self.find_backward(pattern, end, self.len, cancelled),
+                    }
+                }
+            }
+        }
+
+        // First occurrence starting between the offsets.
+        fn find_forward(
+            &mut self,
+            pattern: &[u8],
+            from: u64,
+            to: u64,
+            cancelled: &AtomicBool,
+        ) -> io::Result<Option<u64>> {
+            let mut start = from;
+            while start < to && !cancelled.load(Ordering::Relaxed) {
+                let end = to.min(start + SEARCH_CHUNK);
+                // Chunks overlap so patterns across them are found.
+                let chunk = self.read_at(start, (end - start) as usize + pattern.len() - 1)?;
+                let found = chunk
+                    .windows(pattern.len())
+                    .take((end - start) as usize)
+                    .position(|window| window == pattern);
+                if let Some(index) = found {
+                    return Ok(Some(start + index as u64));
+                }
+                start = end;
+            }
+            Ok(None)
+        }
+
+        // Last occurrence starting between the offsets.
+        fn find_backward(
+            &mut self,
+            pattern: &[u8],
+            from: u64,
+            to: u64,
+            cancelled: &AtomicBool,
+        ) -> io::Result<Option<u64>> {
+            let mut end = to;
+            while end > from && !cancelled.load(Ordering::Relaxed) {
+                let start = from.max(end.saturating_sub(SEARCH_CHUNK));
+                let chunk = self.read_at(start, (end - start) as usize + pattern.len() - 1)?;
+                let found = chunk
+                    .windows(pattern.len())
+                    .take((end - start) as usize)
+                    .rposition(|window| window == pattern);
+                if let Some(index) = found {
+                    return Ok(Some(start + index as u64));
+                }
+                end = start;
+            }
+            Ok(None)
+        }
+    }
+
+    /// Offset typed by the user, in hexadecimal when starting with 0x and
+    /// in decimal otherwise.
+    pub fn parse_offset(offset: &str) -> Option<u64> {
+        let offset = offset.trim();
+        match offset
+            .strip_prefix("0x")
+            .or_else(|| offset.strip_prefix("0X"))
+        {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => offset.parse().ok(),
+        }
+    }
+
+    /// Bytes searched for: hexadecimal bytes such as `de ad be ef`, or
+    /// text, which can be quoted to search for text looking like bytes.
+    pub fn parse_pattern(pattern: &str) -> Vec<u8> {
+        if let Some(text) = pattern
+            .strip_prefix('"')
+            .and_then(|pattern| pattern.strip_suffix('"'))
+        {
+            return text.as_bytes().to_vec();
+        }
+        let digits: String = pattern.split_whitespace().collect();
+        let is_hex = !digits.is_empty()
+            && digits.len() & 1 == 0
+            && digits.chars().all(|ch| ch.is_ascii_hexdigit());
+        match is_hex {
+            true => (0..digits.len())
+                .step_by(2)
+                .map(|index| u8::from_str_radix(&digits[index..index + 2], 16).unwrap())
+                .collect(),
+            false => pattern.as_bytes().to_vec(),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::io::Cursor;
+
+        fn paged_file(content: Vec<u8>) -> PagedFile<Cursor<Vec<u8>>> {
+            let len = content.len() as u64;
+            PagedFile::new(Cursor::new(content), len)
+        }
+
+        #[test]
+        fn test_read() {
+            let content: Vec<u8> = (0..PAGE_SIZE as usize * 3)
+                .map(|i| (i % 251) as u8)
+                .collect();
+            let mut file = paged_file(content.clone());
+            let offset = PAGE_SIZE as usize - 10;
+            assert_eq!(
+                file.read(offset as u64, 20).unwrap(),
+                content[offset..offset + 20]
+            );
+            assert_eq!(file.read(0, 4).unwrap(), content[..4]);
+            let end = content.len() - 5;
+            assert_eq!(file.read(end as u64, 100).unwrap(), content[end..]);
+            assert!(file.read(content.len() as u64 + 1, 10).unwrap().is_empty());
+            assert_eq!(file.pages.len(), 3);
+        }
+
+        #[test]
+        fn test_find() {
+            let mut content = vec![0u8; SEARCH_CHUNK as usize * 2 + 100];
+            let across = SEARCH_CHUNK as usize - 2;
+            content[across..across + 4].copy_from_slice(b"\xde\xad\xbe\xef");
+            content[10..14].copy_from_slice(b"\xde\xad\xbe\xef");
+            let mut file = paged_file(content);
+            let pattern = b"\xde\xad\xbe\xef";
+            let running = AtomicBool::new(false);
+            assert_eq!(file.find(pattern, 0, true, &running).unwrap(), Some(10));
+            assert_eq!(
+                file.find(pattern, 11, true, &running).unwrap(),
+                Some(across as u64)
+            );
+            assert_eq!(
+                file.find(pattern, across as u64 + 1, true, &running)
+                    .unwrap(),
+                Some(10)
+            );
+            assert_eq!(
+                file.find(pattern, across as u64, false, &running).unwrap(),
+                Some(across as u64)
+            );
+            assert_eq!(
+                file.find(pattern, across as u64 - 1, false, &running)
+                    .unwrap(),
+                Some(10)
+            );
+            assert_eq!(
+                file.find(pattern, 9, false, &running).unwrap(),
+                Some(across as u64)
+            );
+            assert_eq!(file.find(b"abc", 0, true, &running).unwrap(), None);
+            assert_eq!(file.find(b"", 0, true, &running).unwrap(), None);
+            let cancelled = AtomicBool::new(true);
+            assert_eq!(file.find(pattern, 0, true, &cancelled).unwrap(), None);
+        }
+
+        #[test]
+        fn test_parse_offset() {
+            assert_eq!(parse_offset("0x1f"), Some(31));
+            assert_eq!(parse_offset(" 42 "), Some(42));
+            assert_eq!(parse_offset("1f"), None);
+        }
+
+        #[test]
+        fn test_parse_pattern() {
+            assert_eq!(parse_pattern("de ad BE EF"), vec![0xde, 0xad, 0xbe, 0xef]);
+            assert_eq!(parse_pattern("ELF"), b"ELF".to_vec());
+            assert_eq!(parse_pattern("\"cafe\""), b"cafe".to_vec());
+            assert_eq!(parse_pattern("abc"), b"abc".to_vec());
+        }
+    }
+}
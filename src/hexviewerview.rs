@@ -0,0 +1,375 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+use crate::command::run;
+use crate::hexviewer::hexviewer::{self, PagedFile};
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::{ColorStyle, Effect};
+use cursive::view::View;
+use cursive::{Printer, Vec2};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+const DEFAULT_ROW_BYTES: usize = 16;
+const MIN_ROW_BYTES: usize = 4;
+const MAX_ROW_BYTES: usize = 64;
+// Bytes added or removed from the rows by + and -.
+const ROW_BYTES_STEP: usize = 4;
+
+// Text typed in the status line.
+enum Prompt {
+    Search(String),
+    Offset(String),
+}
+
+// Viewer showing the bytes of a file in hexadecimal and ASCII.
+pub struct HexViewer {
+    path: String,
+    file: PagedFile<File>,
+    // Offset of the first row shown.
+    top: u64,
+    row_bytes: usize,
+    prompt: Option<Prompt>,
+    pattern: Vec<u8>,
+    // Offset and length of the bytes found or jumped to.
+    matched: Option<(u64, usize)>,
+    message: String,
+    // Set while searching in another thread, to cancel it.
+    searching: Option<Arc<AtomicBool>>,
+    size: Vec2,
+    // Bytes shown, from the top offset.
+    bytes: Vec<u8>,
+}
+
+impl HexViewer {
+    pub fn open(path: &str) -> io::Result<Self> {
+        Ok(HexViewer {
+            path: path.to_string(),
+            file: PagedFile::open(Path::new(path))?,
+            top: 0,
+            row_bytes: DEFAULT_ROW_BYTES,
+            prompt: None,
+            pattern: Vec::new(),
+            matched: None,
+            message: String::new(),
+            searching: None,
+            size: Vec2::zero(),
+            bytes: Vec::new(),
+        })
+    }
+
+    // Rows available for the bytes, the last one being the status line.
+    fn page_rows(&self) -> usize {
+        self.size.y.saturating_sub(1).max(1)
+    }
+
+    fn row_count(&self) -> u64 {
+        let row_bytes = self.row_bytes as u64;
+        // The last row may be partial.
+        match self.file.len() % row_bytes {
+            0 => self.file.len() / row_bytes,
+            _ => self.file.len() / row_bytes + 1,
+        }
+    }
+
+    fn scroll_to_row(&mut self, row: u64) {
+        let last = self.row_count().saturating_sub(self.page_rows() as u64);
+        self.top = row.min(last) * self.row_bytes as u64;
+    }
+
+    fn scroll_down(&mut self, rows: usize) {
+        self.scroll_to_row(self.top / self.row_bytes as u64 + rows as u64);
+    }
+
+    fn scroll_up(&mut self, rows: usize) {
+        self.scroll_to_row((self.top / self.row_bytes as u64).saturating_sub(rows as u64));
+    }
+
+    // Scrolls so the offset is shown, keeping it where it is when it
+    // already is.
+    fn show_offset(&mut self, offset: u64) {
+        let row = offset / self.row_bytes as u64;
+        let top_row = self.top / self.row_bytes as u64;
+        let rows = self.page_rows() as u64;
+        if row < top_row || row >= top_row + rows {
+            self.scroll_to_row(row.saturating_sub(rows / 3));
+        }
+    }
+
+    fn set_row_bytes(&mut self, row_bytes: usize) {
+        self.row_bytes = row_bytes.clamp(MIN_ROW_BYTES, MAX_ROW_BYTES);
+        self.scroll_to_row(self.top / self.row_bytes as u64);
+    }
+
+    // Searches from the last match or the top row, reading the file in
+    // another thread as a miss reads the whole file.
+    fn search(&mut self, forward: bool, skip_current: bool) -> EventResult {
+        if self.pattern.is_empty() {
+            return EventResult::Consumed(None);
+        }
+        let current = self.matched.map(|(offset, _)| offset).unwrap_or(self.top);
+        let start = match (forward, skip_current) {
+            (true, true) => current + 1,
+            (false, true) => match current {
+                0 => self.file.len(),
+                current => current - 1,
+            },
+            (_, false) => current,
+        };
+        self.cancel_search();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.searching = Some(cancelled.clone());
+        let path = self.path.clone();
+        let pattern = self.pattern.clone();
+        EventResult::with_cb(move |s| {
+            let sink = s.cb_sink().clone();
+            let path = path.clone();
+            let pattern = pattern.clone();
+            let cancelled = cancelled.clone();
+            thread::spawn(move || {
+                let found = PagedFile::open(Path::new(&path))
+                    .and_then(|mut file| file.find(&pattern, start, forward, &cancelled));
+                if cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let sent = sink.send(Box::new(move |s| {
+                    s.call_on_name("file_viewer", |viewer: &mut HexViewer| {
+                        viewer.found(&cancelled, found)
+                    });
+                }));
+                if let Err(error) = sent {
+                    log::error!("Cannot send the search result: {:?}", error);
+                }
+            });
+        })
+    }
+
+    fn found(&mut self, search: &Arc<AtomicBool>, found: io::Result<Option<u64>>) {
+        // Another search may have been started since, or another file
+        // viewed.
+        match &self.searching {
+            Some(searching) if Arc::ptr_eq(searching, search) => self.searching = None,
+            _ => return,
+        }
+        match found {
+            Ok(Some(offset)) => {
+                self.matched = Some((offset, self.pattern.len()));
+                self.show_offset(offset);
+                self.message = format!("Match at 0x{:x}", offset);
+            }
+            Ok(None) => self.message = "Pattern not found".to_string(),
+            Err(error) => {
+                log::error!("Cannot search {}: {:?}", self.path, error);
+                self.message = format!("Cannot search: {}", error);
+            }
+        }
+    }
+
+    fn cancel_search(&mut self) {
+        if let Some(cancelled) = self.searching.take() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn go_to_offset(&mut self, offset: &str) {
+        match hexviewer::parse_offset(offset) {
+            Some(offset) if offset < self.file.len() => {
+                self.matched = Some((offset, 1));
+                self.show_offset(offset);
+            }
+            Some(_) => {
+                self.message = format!("The file has {} bytes", self.file.len());
+            }
+            None => self.message = format!("Invalid offset: {}", offset),
+        }
+    }
+
+    fn on_prompt_event(&mut self, event: Event) -> EventResult {
+        let input = match &mut self.prompt {
+            Some(Prompt::Search(input)) | Some(Prompt::Offset(input)) => input,
+            None => return EventResult::Ignored,
+        };
+        match event {
+            Event::Char(ch) => input.push(ch),
+            Event::Key(Key::Backspace) if input.pop().is_none() => self.prompt = None,
+            Event::Key(Key::Backspace) => (),
+            Event::Key(Key::Esc) | Event::CtrlChar('c') | Event::CtrlChar('g') => {
+                self.prompt = None
+            }
+            Event::Key(Key::Enter) => match self.prompt.take() {
+                Some(Prompt::Search(pattern)) if !pattern.is_empty() => {
+                    self.pattern = hexviewer::parse_pattern(&pattern);
+                    self.matched = None;
+                    return self.search(true, false);
+                }
+                // An empty search repeats the last one.
+                Some(Prompt::Search(_)) => return self.search(true, true),
+                Some(Prompt::Offset(offset)) => self.go_to_offset(&offset),
+                None => (),
+            },
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+
+    fn is_matched(&self, offset: u64) -> bool {
+        match self.matched {
+            Some((start, len)) => offset >= start && offset < start + len as u64,
+            None => false,
+        }
+    }
+
+    // Hexadecimal digits of the offsets, enough for the last one.
+    fn offset_width(&self) -> usize {
+        format!("{:x}", self.file.len()).len().max(8)
+    }
+
+    fn status(&self) -> String {
+        match &self.prompt {
+            Some(Prompt::Search(input)) => format!("/{}", input),
+            Some(Prompt::Offset(input)) => format!(":{}", input),
+            None => format!(
+                "{}  0x{:x}/0x{:x}  {} bytes per row  {}",
+                self.path,
+                self.top,
+                self.file.len(),
+                self.row_bytes,
+                match self.searching {
+                    Some(_) => "Searching...",
+                    None => &self.message,
+                }
+            ),
+        }
+    }
+}
+
+impl Drop for HexViewer {
+    fn drop(&mut self) {
+        self.cancel_search();
+    }
+}
+
+impl View for HexViewer {
+    fn draw(&self, printer: &Printer) {
+        let offset_width = self.offset_width();
+        let characters_x = offset_width + 2 + self.row_bytes * 3 + 1;
+        for (y, row) in self.bytes.chunks(self.row_bytes).enumerate() {
+            let row_offset = self.top + (y * self.row_bytes) as u64;
+            printer.with_color(ColorStyle::secondary(), |printer| {
+                printer.print(
+                    (0, y),
+                    &format!("{:0width$x}", row_offset, width = offset_width),
+                )
+            });
+            for (index, byte) in row.iter().enumerate() {
+                let effect = match self.is_matched(row_offset + index as u64) {
+                    true => Effect::Reverse,
+                    false => Effect::Simple,
+                };
+                let character = match byte.is_ascii_graphic() || *byte == b' ' {
+                    true => *byte as char,
+                    false => '.',
+                };
+                printer.with_effect(effect, |printer| {
+                    printer.print((offset_width + 2 + index * 3, y), &format!("{:02x}", byte));
+                    printer.print((characters_x + index, y), &character.to_string());
+                });
+            }
+        }
+        let y = printer.size.y.saturating_sub(1);
+        printer.with_effect(Effect::Reverse, |printer| {
+            printer.print_hline((0, y), printer.size.x, " ");
+            printer.print((0, y), &self.status());
+        });
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        self.size = size;
+        let count = self.page_rows() * self.row_bytes;
+        self.bytes = match self.file.read(self.top, count) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                log::error!("Cannot read {}: {:?}", self.path, error);
+                self.message = format!("Cannot read: {}", error);
+                Vec::new()
+            }
+        };
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        constraint
+    }
+
+    fn take_focus(&mut self, _source: cursive::direction::Direction) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        if self.prompt.is_some() {
+            return self.on_prompt_event(event);
+        }
+        self.message.clear();
+        let page = self.page_rows();
+        match event {
+            Event::Char('q') | Event::Key(Key::Esc) => {
+                return EventResult::with_cb(run::close_viewer)
+            }
+            Event::Char('j') | Event::Key(Key::Down) | Event::Key(Key::Enter) => {
+                self.scroll_down(1)
+            }
+            Event::Char('k') | Event::Key(Key::Up) => self.scroll_up(1),
+            Event::Char(' ') | Event::Char('f') | Event::Key(Key::PageDown) => {
+                self.scroll_down(page)
+            }
+            Event::Char('b') | Event::Key(Key::PageUp) => self.scroll_up(page),
+            Event::Char('g') | Event::Key(Key::Home) => self.scroll_to_row(0),
+            Event::Char('G') | Event::Key(Key::End) => self.scroll_to_row(self.row_count()),
+            Event::Char('+') => self.set_row_bytes(self.row_bytes + ROW_BYTES_STEP),
+            Event::Char('-') => self.set_row_bytes(self.row_bytes.saturating_sub(ROW_BYTES_STEP)),
+            Event::Char('/') => self.prompt = Some(Prompt::Search(String::new())),
+            Event::Char(':') => self.prompt = Some(Prompt::Offset(String::new())),
+            Event::Char('n') => return self.search(true, true),
+            Event::Char('N') => return self.search(false, true),
+            Event::CtrlChar('c') if self.searching.is_some() => self.cancel_search(),
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+}
@@ -0,0 +1,279 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod highlight {
+    use crate::command::run;
+    use crate::executables::executables;
+    use crate::lexer::lexer;
+    use std::path::Path;
+
+    // Words run by the shell itself rather than found on the PATH.
+    const SHELL_BUILTINS: [&str; 38] = [
+        "!", ".", ":", "[", "alias", "break", "case", "command", "continue", "do", "done", "echo",
+        "elif", "else", "esac", "eval", "exec", "exit", "export", "false", "fi", "for", "if",
+        "printf", "pwd", "read", "return", "set", "shift", "source", "test", "then", "true",
+        "type", "umask", "unset", "until", "while",
+    ];
+
+    // Tokens after which a new command starts.
+    const SEPARATORS: [&str; 9] = ["|", "||", "&&", ";", "&", "do", "then", "else", "!"];
+
+    // Commands running the command given as their first argument.
+    const PREFIXES: [&str; 6] = ["term", "sudo", "exec", "command", "nohup", "time"];
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Kind {
+        Command,
+        UnknownCommand,
+        Path,
+        String,
+        Variable,
+    }
+
+    // Part of the command line to highlight, in bytes.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Span {
+        pub start: usize,
+        pub end: usize,
+        pub kind: Kind,
+    }
+
+    /// Finds the parts of the command line to highlight. Later spans take
+    /// precedence over earlier ones where they overlap.
+    pub fn highlight<C, P>(input: &str, is_command: C, is_path: P) -> Vec<Span>
+    where
+        C: Fn(&str) -> bool,
+        P: Fn(&str) -> bool,
+    {
+        let mut spans = Vec::new();
+        let (tokens, _error) = lexer::tokenize_partial(input);
+        let mut command_position = true;
+        let mut previous_end = 0;
+        for token in tokens {
+            let gap = &input[previous_end..token.start];
+            // A new line not escaped ends the command.
+            if gap.contains('\n') && !gap.contains('\\') {
+                command_position = true;
+            }
+            previous_end = token.end;
+            let word = &input[token.start..token.end];
+            if SEPARATORS.contains(&word) {
+                command_position = true;
+                continue;
+            }
+            if command_position && is_assignment(word) {
+                continue;
+            }
+            let kind = if command_position {
+                // Forcing the full terminal with ! does not change the command.
+                let command = match token.value.strip_prefix('!') {
+                    Some(command) if !command.is_empty() => command,
+                    _ => &token.value,
+                };
+                match is_command(command) {
+                    true => Kind::Command,
+                    false => Kind::UnknownCommand,
+                }
+            } else if !token.value.is_empty() && is_path(&token.value) {
+                Kind::Path
+            } else {
+                command_position = word.ends_with(';');
+                continue;
+            };
+            spans.push(Span {
+                start: token.start,
+                end: token.end,
+                kind,
+            });
+            command_position = word.ends_with(';')
+                || (kind == Kind::Command && PREFIXES.contains(&token.value.as_str()));
+        }
+        spans.extend(quotes_and_variables(input));
+        spans
+    }
+
+    /// Tells whether the command is run by manette or the shell, or found
+    /// on the PATH.
+    pub fn is_command(command: &str) -> bool {
+        if run::BUILTINS.contains(&command) || SHELL_BUILTINS.contains(&command) {
+            return true;
+        }
+        if command.contains('/') {
+            return executables::is_executable(Path::new(command));
+        }
+        executables::contains(command)
+    }
+
+    pub fn is_path(path: &str) -> bool {
+        Path::new(path).exists()
+    }
+
+    // Variable assignment before a command, like NAME=value.
+    fn is_assignment(word: &str) -> bool {
+        match word.find('=') {
+            Some(index) if index > 0 => word[..index]
+                .chars()
+                .all(|ch| ch.is_alphanumeric() || ch == '_'),
+            _ => false,
+        }
+    }
+
+    // Spans of the quoted strings and of the variables, outside of single
+    // quotes.
+    fn quotes_and_variables(input: &str) -> Vec<Span> {
+        let mut strings = Vec::new();
+        let mut variables = Vec::new();
+        let mut chars = input.char_indices().peekable();
+        let mut quote_start: Option<(usize, char)> = None;
+        while let Some((index, ch)) = chars.next() {
+            match (quote_start, ch) {
+                (Some((start, '\'')), '\'') | (Some((start, '"')), '"') => {
+                    strings.push(Span {
+                        start,
+                        end: index + 1,
+                        kind: Kind::String,
+                    });
+                    quote_start = None;
+                }
+                (Some((_, '\'')), _) => (),
+                (_, '\\') => {
+                    chars.next();
+                }
+                (None, '\'') | (None, '"') => quote_start = Some((index, ch)),
+                (_, '$') => {
+                    let mut end = index + 1;
+                    if let Some((_, '{')) = chars.peek() {
+                        for (next_index, next) in chars.by_ref() {
+                            end = next_index + next.len_utf8();
+                            if next == '}' {
+                                break;
+                            }
+                        }
+                    } else {
+                        while let Some((next_index, next)) = chars.peek() {
+                            if !(next.is_alphanumeric() || *next == '_') {
+                                break;
+                            }
+                            end = next_index + next.len_utf8();
+                            chars.next();
+                        }
+                    }
+                    if end > index + 1 {
+                        variables.push(Span {
+                            start: index,
+                            end,
+                            kind: Kind::Variable,
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+        // Strings still being typed run to the end of the line.
+        if let Some((start, _)) = quote_start {
+            strings.push(Span {
+                start,
+                end: input.len(),
+                kind: Kind::String,
+            });
+        }
+        strings.append(&mut variables);
+        strings
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn kinds(input: &str) -> Vec<(&str, Kind)> {
+            highlight(input, |command| command != "sl", |path| path == "/tmp")
+                .into_iter()
+                .map(|span| (&input[span.start..span.end], span.kind))
+                .collect()
+        }
+
+        #[test]
+        fn test_commands() {
+            assert_eq!(
+                kinds("ls /tmp | sl -l; A=1 cd"),
+                vec![
+                    ("ls", Kind::Command),
+                    ("/tmp", Kind::Path),
+                    ("sl", Kind::UnknownCommand),
+                    ("cd", Kind::Command),
+                ]
+            );
+            assert_eq!(
+                kinds("term sl"),
+                vec![("term", Kind::Command), ("sl", Kind::UnknownCommand)]
+            );
+            assert_eq!(kinds("!vim"), vec![("!vim", Kind::Command)]);
+            assert_eq!(
+                kinds("for i in 1\ndo sl\ndone"),
+                vec![
+                    ("for", Kind::Command),
+                    ("sl", Kind::UnknownCommand),
+                    ("done", Kind::Command),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_strings_and_variables() {
+            assert_eq!(
+                kinds("echo '$A' \"b $B ${C}\" $D_1 \\$E 'f"),
+                vec![
+                    ("echo", Kind::Command),
+                    ("'$A'", Kind::String),
+                    ("\"b $B ${C}\"", Kind::String),
+                    ("'f", Kind::String),
+                    ("$B", Kind::Variable),
+                    ("${C}", Kind::Variable),
+                    ("$D_1", Kind::Variable),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_is_command() {
+            assert!(is_command("cd"));
+            assert!(is_command("sh"));
+            assert!(is_command("/bin/sh"));
+            assert!(!is_command("/bin"));
+            assert!(!is_command("manette-missing-command"));
+        }
+    }
+}
@@ -0,0 +1,317 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod history {
+    use crate::userenv::userenv;
+    use anyhow::Result;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    // Number of commands kept in the history.
+    pub const HISTORY_SIZE: usize = 1000;
+
+    // Commands previously submitted, oldest first.
+    pub struct History {
+        entries: Vec<String>,
+        // File the history is saved to, if any.
+        path: Option<PathBuf>,
+        max_size: usize,
+        // Entry shown while navigating, None when editing a new line.
+        position: Option<usize>,
+        // Line being edited when navigation started.
+        draft: String,
+    }
+
+    impl History {
+        pub fn new(max_size: usize) -> Self {
+            History {
+                entries: Vec::new(),
+                path: None,
+                max_size,
+                position: None,
+                draft: String::new(),
+            }
+        }
+
+        /// Loads the history saved in the user data directory.
+        pub fn load_default() -> Self {
+            let path = userenv::data_dir().join("manette").join("history");
+            History::load(path, HISTORY_SIZE)
+        }
+
+        pub fn load(path: PathBuf, max_size: usize) -> Self {
+            let mut history = History::new(max_size);
+            match fs::read_to_string(&path) {
+                Ok(content) => {
+                    let lines: Vec<&str> = content.lines().collect();
+                    for line in &lines {
+                        history.push(decode(line));
+                    }
+                    history.path = Some(path);
+                    // Compact the file once it holds too many commands.
+                    if lines.len() > history.entries.len() {
+                        if let Err(error) = history.save() {
+                            log::error!("Cannot save history: {:?}", error);
+                        }
+                    }
+                }
+                Err(error) => {
+                    log::debug!("Cannot read history {:?}: {:?}", path, error);
+                    history.path = Some(path);
+                }
+            }
+            history
+        }
+
+        /// Records a submitted command and saves it.
+        pub fn add(&mut self, command: &str) {
+            self.reset_navigation();
+            if command.trim().is_empty() {
+                return;
+            }
+            self.push(command.to_string());
+            if let Err(error) = self.append_to_file(command) {
+                log::error!("Cannot save history: {:?}", error);
+            }
+        }
+
+        /// Moves to the previous entry, keeping the current line to come
+        /// back to it.
+        pub fn previous(&mut self, current: &str) -> Option<&str> {
+            let position = match self.position {
+                None => {
+                    self.draft = current.to_string();
+                    self.entries.len()
+                }
+                Some(position) => position,
+            };
+            if position == 0 {
+                return None;
+            }
+            self.position = Some(position - 1);
+            Some(&self.entries[position - 1])
+        }
+
+        /// Moves to the next entry, or back to the line being edited.
+        pub fn next(&mut self) -> Option<&str> {
+            let position = self.position? + 1;
+            if position < self.entries.len() {
+                self.position = Some(position);
+                Some(&self.entries[position])
+            } else {
+                self.position = None;
+                Some(&self.draft)
+            }
+        }
+
+        /// Previously submitted commands, oldest first.
+        pub fn entries(&self) -> &[String] {
+            &self.entries
+        }
+
+        /// Most recent entry starting with the prefix, to complete it.
+        pub fn suggest(&self, prefix: &str) -> Option<&str> {
+            self.entries
+                .iter()
+                .rev()
+                .find(|entry| entry.len() > prefix.len() && entry.starts_with(prefix))
+                .map(String::as_str)
+        }
+
+        pub fn reset_navigation(&mut self) {
+            self.position = None;
+            self.draft.clear();
+        }
+
+        // Adds the command as the most recent entry, removing its previous
+        // occurrence.
+        fn push(&mut self, command: String) {
+            self.entries.retain(|entry| *entry != command);
+            self.entries.push(command);
+            if self.entries.len() > self.max_size {
+                let extra = self.entries.len() - self.max_size;
+                self.entries.drain(..extra);
+            }
+        }
+
+        fn append_to_file(&self, command: &str) -> Result<()> {
+            if let Some(path) = &self.path {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", encode(command))?;
+            }
+            Ok(())
+        }
+
+        fn save(&self) -> Result<()> {
+            if let Some(path) = &self.path {
+                let content: String = self
+                    .entries
+                    .iter()
+                    .map(|entry| format!("{}\n", encode(entry)))
+                    .collect();
+                let temp_path = path.with_extension("tmp");
+                fs::write(&temp_path, content)?;
+                fs::rename(temp_path, path)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Finds the entries matching the query, most recent first. Entries
+    /// containing the query come before the ones only containing its
+    /// characters in order. Matching ignores case.
+    pub fn search<'a>(entries: &'a [String], query: &str) -> Vec<&'a str> {
+        let query = query.to_lowercase();
+        let mut substring_matches = Vec::new();
+        let mut fuzzy_matches = Vec::new();
+        for entry in entries.iter().rev() {
+            let lowercase_entry = entry.to_lowercase();
+            if lowercase_entry.contains(&query) {
+                substring_matches.push(entry.as_str());
+            } else if fuzzy_match(&lowercase_entry, &query) {
+                fuzzy_matches.push(entry.as_str());
+            }
+        }
+        substring_matches.append(&mut fuzzy_matches);
+        substring_matches
+    }
+
+    // Tells whether all the query characters appear in the entry, in order.
+    fn fuzzy_match(entry: &str, query: &str) -> bool {
+        let mut chars = entry.chars();
+        query.chars().all(|ch| chars.any(|entry_ch| entry_ch == ch))
+    }
+
+    // Entries are saved one per line, so new lines are escaped.
+    fn encode(command: &str) -> String {
+        command.replace('\\', "\\\\").replace('\n', "\\n")
+    }
+
+    fn decode(line: &str) -> String {
+        let mut command = String::with_capacity(line.len());
+        let mut chars = line.chars();
+        while let Some(ch) = chars.next() {
+            match (ch, chars.clone().next()) {
+                ('\\', Some('n')) => {
+                    command.push('\n');
+                    chars.next();
+                }
+                ('\\', Some('\\')) => {
+                    command.push('\\');
+                    chars.next();
+                }
+                _ => command.push(ch),
+            }
+        }
+        command
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::{env, process};
+
+        #[test]
+        fn test_navigation() {
+            let mut history = History::new(10);
+            history.add("ls");
+            history.add("make");
+            assert_eq!(history.previous("draft"), Some("make"));
+            assert_eq!(history.previous("make"), Some("ls"));
+            assert_eq!(history.previous("ls"), None);
+            assert_eq!(history.next(), Some("make"));
+            assert_eq!(history.next(), Some("draft"));
+            assert_eq!(history.next(), None);
+        }
+
+        #[test]
+        fn test_deduplication_and_limit() {
+            let mut history = History::new(3);
+            for command in ["a", "b", "a", "c", "d", " "].iter() {
+                history.add(command);
+            }
+            assert_eq!(history.entries(), ["a", "c", "d"]);
+        }
+
+        #[test]
+        fn test_suggest() {
+            let mut history = History::new(10);
+            history.add("cargo build --release");
+            history.add("cargo test");
+            history.add("cargo");
+            assert_eq!(history.suggest("cargo"), Some("cargo test"));
+            assert_eq!(history.suggest("cargo b"), Some("cargo build --release"));
+            assert_eq!(history.suggest("cargo test"), None);
+            assert_eq!(history.suggest("make"), None);
+        }
+
+        #[test]
+        fn test_search() {
+            let entries: Vec<String> =
+                ["git status", "ls -l", "git commit", "cargo test", "Git log"]
+                    .iter()
+                    .map(|entry| entry.to_string())
+                    .collect();
+            assert_eq!(
+                search(&entries, "git"),
+                ["Git log", "git commit", "git status"]
+            );
+            // Substring matches come first even when older.
+            assert_eq!(search(&entries, "co"), ["git commit", "cargo test"]);
+            assert_eq!(search(&entries, "").len(), 5);
+            assert!(search(&entries, "make").is_empty());
+        }
+
+        #[test]
+        fn test_persistence() {
+            let dir = env::temp_dir().join(format!("manette-test-persistence-{}", process::id()));
+            let path = dir.join("test").join("history");
+            let mut history = History::load(path.clone(), 2);
+            history.add("ls");
+            history.add("echo 'a\\nb'\nfor i in 1 2");
+            history.add("ls");
+            let history = History::load(path.clone(), 2);
+            assert_eq!(history.entries(), ["echo 'a\\nb'\nfor i in 1 2", "ls"]);
+            // The file was compacted when loaded.
+            assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
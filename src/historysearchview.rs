@@ -0,0 +1,189 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+use crate::history::history;
+use crate::view::CliView;
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::Effect;
+use cursive::view::View;
+use cursive::{Printer, Vec2};
+use std::rc::Rc;
+use unicode_width::UnicodeWidthStr;
+
+// Maximum number of matches shown at once.
+const MAX_ROWS: usize = 10;
+
+// Popup searching the history as the query is typed, like the reverse
+// incremental search of bash.
+pub struct HistorySearchPopup {
+    // Previously submitted commands, oldest first.
+    entries: Rc<Vec<String>>,
+    query: String,
+    matches: Vec<String>,
+    focus: usize,
+}
+
+impl HistorySearchPopup {
+    pub fn new(entries: Rc<Vec<String>>) -> Self {
+        let mut popup = HistorySearchPopup {
+            entries,
+            query: String::new(),
+            matches: Vec::new(),
+            focus: 0,
+        };
+        popup.search();
+        popup
+    }
+
+    fn search(&mut self) {
+        self.matches = history::search(&self.entries, &self.query)
+            .into_iter()
+            .map(|entry| entry.to_string())
+            .collect();
+        self.focus = 0;
+    }
+
+    fn push(&mut self, ch: char) {
+        self.query.push(ch);
+        self.search();
+    }
+
+    fn backspace(&mut self) {
+        if self.query.pop().is_some() {
+            self.search();
+        }
+    }
+
+    // Moves to the next older match, going back to the most recent one
+    // after the last.
+    fn cycle(&mut self) {
+        if !self.matches.is_empty() {
+            self.focus = (self.focus + 1) % self.matches.len();
+        }
+    }
+
+    fn previous(&mut self) {
+        if self.focus > 0 {
+            self.focus -= 1;
+        }
+    }
+
+    fn submit(&mut self) -> EventResult {
+        let entry = match self.matches.get(self.focus) {
+            Some(entry) => entry.clone(),
+            None => return self.dismiss(),
+        };
+        EventResult::with_cb(move |s| {
+            s.pop_layer();
+            let content = entry.clone();
+            s.call_on_name("cli_input", |view: &mut CliView| {
+                view.set_content(content);
+            });
+        })
+    }
+
+    fn dismiss(&mut self) -> EventResult {
+        EventResult::with_cb(|s| {
+            s.pop_layer();
+        })
+    }
+
+    fn header(&self) -> String {
+        match self.matches.is_empty() {
+            true => format!("(failed reverse-i-search)`{}': ", self.query),
+            false => format!("(reverse-i-search)`{}': ", self.query),
+        }
+    }
+
+    // Matches are shown on a single line.
+    fn label(entry: &str) -> String {
+        entry.replace('\n', " ")
+    }
+}
+
+impl View for HistorySearchPopup {
+    fn draw(&self, printer: &Printer) {
+        if !printer.size.fits((3, 3)) {
+            return;
+        }
+        printer.print_box((0, 0), printer.size, false);
+        let printer = printer.shrinked_centered((2, 2));
+        printer.with_effect(Effect::Bold, |printer| {
+            printer.print((1, 0), &self.header());
+        });
+
+        // Keep the focused match visible.
+        let rows = printer.size.y.saturating_sub(1);
+        let first = (self.focus + 1).saturating_sub(rows);
+        for (row, entry) in self.matches.iter().enumerate().skip(first).take(rows) {
+            printer.with_selection(row == self.focus, |printer| {
+                let y = row - first + 1;
+                printer.print_hline((0, y), printer.size.x, " ");
+                printer.print((1, y), &Self::label(entry));
+            });
+        }
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        let width = self
+            .matches
+            .iter()
+            .map(|entry| Self::label(entry).width())
+            .chain(std::iter::once(self.header().width()))
+            .max()
+            .unwrap_or(0)
+            + 2;
+        let height = 1 + self.matches.len().min(MAX_ROWS);
+        Vec2::new(width, height)
+            .map(|size| size + 2)
+            .or_min(constraint)
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Char(ch) => self.push(ch),
+            Event::Key(Key::Backspace) | Event::CtrlChar('h') => self.backspace(),
+            Event::CtrlChar('r') | Event::Key(Key::Down) => self.cycle(),
+            Event::CtrlChar('s') | Event::Key(Key::Up) => self.previous(),
+            Event::Key(Key::Enter) => return self.submit(),
+            Event::Key(Key::Esc) | Event::CtrlChar('g') | Event::CtrlChar('c') => {
+                return self.dismiss()
+            }
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+}
@@ -0,0 +1,341 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod lexer {
+    use crate::userenv::userenv;
+    use std::iter::Peekable;
+    use std::str::CharIndices;
+    use std::{env, error, fmt};
+
+    // A word of the command line, after quote removal and expansion.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Token {
+        pub value: String,
+        // Byte offsets of the word in the command line.
+        pub start: usize,
+        pub end: usize,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum LexError {
+        UnclosedQuote(char),
+        TrailingBackslash,
+    }
+
+    impl fmt::Display for LexError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                LexError::UnclosedQuote(quote) => write!(f, "Unclosed quote {}", quote),
+                LexError::TrailingBackslash => write!(f, "Trailing backslash"),
+            }
+        }
+    }
+
+    impl error::Error for LexError {}
+
+    /// Splits the command line into words, like a shell would.
+    pub fn split(input: &str) -> Result<Vec<String>, LexError> {
+        Ok(tokenize(input)?
+            .into_iter()
+            .map(|token| token.value)
+            .collect())
+    }
+
+    pub fn tokenize(input: &str) -> Result<Vec<Token>, LexError> {
+        match tokenize_partial(input) {
+            (tokens, None) => Ok(tokens),
+            (_, Some(error)) => Err(error),
+        }
+    }
+
+    /// Tokenizes a command line which may still be being typed: the last
+    /// token is returned even if its quote is not closed yet, along with
+    /// the error.
+    pub fn tokenize_partial(input: &str) -> (Vec<Token>, Option<LexError>) {
+        let mut tokens = Vec::new();
+        let mut chars = input.char_indices().peekable();
+        let mut error = None;
+        loop {
+            while let Some(&(_, ch)) = chars.peek() {
+                if ch == '\\' && next_is_newline(&chars) {
+                    chars.next();
+                } else if !ch.is_whitespace() {
+                    break;
+                }
+                chars.next();
+            }
+            let start = match chars.peek() {
+                Some((start, _)) => *start,
+                None => break,
+            };
+            let (value, word_error) = read_word(&mut chars);
+            let end = chars.peek().map(|(end, _)| *end).unwrap_or(input.len());
+            tokens.push(Token { value, start, end });
+            if word_error.is_some() {
+                error = word_error;
+                break;
+            }
+        }
+        (tokens, error)
+    }
+
+    /// Tells whether the command line continues on the next line, after
+    /// an unclosed quote or a trailing backslash.
+    pub fn is_incomplete(input: &str) -> bool {
+        tokenize_partial(input).1.is_some()
+    }
+
+    /// Tells whether the command line ends with a space separating it
+    /// from a new, still empty, word.
+    pub fn ends_with_separator(input: &str) -> bool {
+        match tokenize_partial(input) {
+            (tokens, None) => match tokens.last() {
+                Some(token) => token.end < input.len(),
+                None => !input.is_empty(),
+            },
+            (_, Some(_)) => false,
+        }
+    }
+
+    /// Escapes the word so it is read back as a single token.
+    pub fn quote(word: &str) -> String {
+        let mut quoted = String::with_capacity(word.len());
+        for ch in word.chars() {
+            if ch.is_whitespace() || "\\'\"$`&|;<>()*?[]#~!{}".contains(ch) {
+                quoted.push('\\');
+            }
+            quoted.push(ch);
+        }
+        quoted
+    }
+
+    fn read_word(chars: &mut Peekable<CharIndices>) -> (String, Option<LexError>) {
+        let mut value = String::new();
+        if let Some((_, '~')) = chars.peek() {
+            let mut lookahead = chars.clone();
+            lookahead.next();
+            match lookahead.peek() {
+                None | Some((_, '/')) => {
+                    chars.next();
+                    value.push_str(&userenv::home());
+                }
+                Some((_, ch)) if ch.is_whitespace() => {
+                    chars.next();
+                    value.push_str(&userenv::home());
+                }
+                _ => (),
+            }
+        }
+        while let Some((_, ch)) = chars.peek() {
+            let ch = *ch;
+            if ch.is_whitespace() {
+                break;
+            }
+            chars.next();
+            match ch {
+                // A backslash before a new line continues the line.
+                '\\' => match chars.next() {
+                    Some((_, '\n')) => (),
+                    Some((_, escaped)) => value.push(escaped),
+                    None => return (value, Some(LexError::TrailingBackslash)),
+                },
+                '\'' => loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, quoted)) => value.push(quoted),
+                        None => return (value, Some(LexError::UnclosedQuote('\''))),
+                    }
+                },
+                '"' => loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.peek() {
+                            Some((_, '\n')) => {
+                                chars.next();
+                            }
+                            Some((_, escaped)) if "\"\\$`".contains(*escaped) => {
+                                value.push(*escaped);
+                                chars.next();
+                            }
+                            Some(_) => value.push('\\'),
+                            None => return (value, Some(LexError::UnclosedQuote('"'))),
+                        },
+                        Some((_, '$')) => expand_variable(chars, &mut value),
+                        Some((_, quoted)) => value.push(quoted),
+                        None => return (value, Some(LexError::UnclosedQuote('"'))),
+                    }
+                },
+                '$' => expand_variable(chars, &mut value),
+                _ => value.push(ch),
+            }
+        }
+        (value, None)
+    }
+
+    fn next_is_newline(chars: &Peekable<CharIndices>) -> bool {
+        let mut lookahead = chars.clone();
+        lookahead.next();
+        matches!(lookahead.peek(), Some((_, '\n')))
+    }
+
+    // Expands the variable following a $ sign, as $NAME or ${NAME}.
+    fn expand_variable(chars: &mut Peekable<CharIndices>, value: &mut String) {
+        let mut name = String::new();
+        if let Some((_, '{')) = chars.peek() {
+            let mut lookahead = chars.clone();
+            lookahead.next();
+            for (_, ch) in lookahead.by_ref() {
+                if ch == '}' {
+                    *chars = lookahead;
+                    value.push_str(&env::var(&name).unwrap_or_default());
+                    return;
+                }
+                name.push(ch);
+            }
+            // Not a variable without the closing brace.
+            value.push('$');
+            return;
+        }
+        while let Some((_, ch)) = chars.peek() {
+            if !(ch.is_alphanumeric() || *ch == '_') {
+                break;
+            }
+            name.push(*ch);
+            chars.next();
+        }
+        if name.is_empty() {
+            value.push('$');
+        } else {
+            value.push_str(&env::var(&name).unwrap_or_default());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_split_quotes() {
+            assert_eq!(
+                split("cd \"My Documents\"").unwrap(),
+                vec!["cd", "My Documents"]
+            );
+            assert_eq!(
+                split("cd My\\ Documents").unwrap(),
+                vec!["cd", "My Documents"]
+            );
+            assert_eq!(
+                split("echo 'a \"b\" $HOME'").unwrap(),
+                vec!["echo", "a \"b\" $HOME"]
+            );
+            assert_eq!(
+                split("echo \"a \\\"b\\\"\"").unwrap(),
+                vec!["echo", "a \"b\""]
+            );
+            assert_eq!(split("a''b \"\"").unwrap(), vec!["ab", ""]);
+            assert!(split("  ").unwrap().is_empty());
+        }
+
+        #[test]
+        fn test_split_errors() {
+            assert_eq!(split("cd \"My"), Err(LexError::UnclosedQuote('"')));
+            assert_eq!(split("cd 'My"), Err(LexError::UnclosedQuote('\'')));
+            assert_eq!(split("cd My\\"), Err(LexError::TrailingBackslash));
+        }
+
+        #[test]
+        fn test_continuation() {
+            assert_eq!(
+                split("echo a \\\n  b\\\nc \"d\\\ne\"").unwrap(),
+                vec!["echo", "a", "bc", "de"]
+            );
+            assert!(is_incomplete("echo 'a"));
+            assert!(is_incomplete("echo a \\"));
+            assert!(!is_incomplete("echo a \\\n"));
+            assert!(!is_incomplete("for i in 1 2\ndo echo $i\ndone"));
+        }
+
+        #[test]
+        fn test_expansion() {
+            env::set_var("MANETTE_LEXER_TEST", "value");
+            assert_eq!(
+                split("$MANETTE_LEXER_TEST/a ${MANETTE_LEXER_TEST}b \"$MANETTE_LEXER_TEST c\"")
+                    .unwrap(),
+                vec!["value/a", "valueb", "value c"]
+            );
+            assert_eq!(split("$ \\$HOME").unwrap(), vec!["$", "$HOME"]);
+            let home = userenv::home();
+            assert_eq!(
+                split("cd ~/dir ~ a~").unwrap(),
+                vec![
+                    "cd".to_string(),
+                    format!("{}/dir", home),
+                    home,
+                    "a~".to_string()
+                ]
+            );
+        }
+
+        #[test]
+        fn test_tokenize_partial() {
+            let (tokens, error) = tokenize_partial("cd \"My Do");
+            assert_eq!(error, Some(LexError::UnclosedQuote('"')));
+            assert_eq!(
+                tokens[1],
+                Token {
+                    value: String::from("My Do"),
+                    start: 3,
+                    end: 9
+                }
+            );
+        }
+
+        #[test]
+        fn test_ends_with_separator() {
+            assert!(ends_with_separator("ls "));
+            assert!(ends_with_separator("ls a\\  "));
+            assert!(!ends_with_separator("ls a\\ "));
+            assert!(!ends_with_separator("ls"));
+            assert!(!ends_with_separator(""));
+        }
+
+        #[test]
+        fn test_quote() {
+            assert_eq!(quote("My Documents"), "My\\ Documents");
+            assert_eq!(split(&quote("it's $1")).unwrap(), vec!["it's $1"]);
+        }
+    }
+}
@@ -35,24 +35,60 @@ knowledge of the CeCILL license and that you accept its terms.
 */
 
 use clap::ArgMatches;
+use cursive::event::Event;
 use cursive::theme::{Color, PaletteColor, Theme};
-use cursive::views::{DummyView, LinearLayout, Panel};
+use cursive::views::{LinearLayout, Panel, ResizedView, TextView};
 use cursive::{Cursive, CursiveExt};
 use cursive_core::view::Nameable;
 use cursive_flexi_logger_view::FlexiLoggerView;
 use flexi_logger::{LogTarget, Logger};
-use std::{env, error::Error, path::PathBuf};
+use std::error::Error;
+use std::time::SystemTime;
 
+#[allow(clippy::module_inception)]
 mod autocomplete;
 mod autocompleteview;
 mod command;
+#[allow(clippy::module_inception)]
+mod edit;
+#[allow(clippy::module_inception)]
+mod executables;
 mod file;
+#[allow(clippy::module_inception)]
+mod fileviewer;
+mod fileviewerview;
+#[allow(clippy::module_inception)]
+mod hexviewer;
+mod hexviewerview;
+#[allow(clippy::module_inception)]
+mod highlight;
+#[allow(clippy::module_inception)]
+mod history;
+mod historysearchview;
+#[allow(clippy::module_inception)]
+mod lexer;
+#[allow(clippy::module_inception)]
+mod ls;
+#[allow(clippy::module_inception)]
+mod opener;
+#[allow(clippy::module_inception)]
+mod preview;
+mod terminalview;
 mod ui;
+#[allow(clippy::module_inception)]
 mod userenv;
+#[allow(clippy::module_inception)]
+mod vi;
 mod view;
+#[allow(clippy::module_inception)]
+mod watcher;
 
+use crate::command::job::Jobs;
 use crate::command::run;
+use crate::history::history::History;
+use crate::ls::ls::LsOptions;
 use crate::view::CliView;
+use crate::watcher::watcher::DirectoryWatcher;
 
 pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     let user_input = move |s: &mut Cursive, command: &str| {
@@ -63,6 +99,7 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     };
 
     let mut siv = Cursive::new();
+    siv.set_user_data(RunState::new());
 
     Logger::with_env_or_str("info, manette = debug")
         .log_target(LogTarget::FileAndWriter(
@@ -77,14 +114,26 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     let theme = custom_theme_from_cursive(&siv);
     siv.set_theme(theme);
     siv.add_global_callback('q', |s| s.quit());
+    // Ctrl-C cancels the running command instead of exiting.
+    siv.clear_global_callbacks(Event::CtrlChar('c'));
+    siv.add_global_callback(Event::CtrlChar('c'), run::cancel_command);
     let mut layout = LinearLayout::vertical()
-        .child(CliView::new().on_submit(user_input).with_name("cli_input"))
-        .child(DummyView)
+        .child(
+            CliView::new()
+                .on_submit(user_input)
+                .history(History::load_default())
+                .vi_mode(config.vi)
+                .with_name("cli_input"),
+        )
+        .child(ResizedView::with_fixed_height(
+            1,
+            TextView::empty().with_name("notification"),
+        ))
         .child(LinearLayout::vertical().with_name("command_layout"));
     if config.debug {
         layout.add_child(FlexiLoggerView::scrollable());
     }
-    siv.add_layer(Panel::new(layout).title("manette"));
+    siv.add_layer(Panel::new(layout).title("manette").with_name("main_panel"));
     command::run::run_command("ls", &mut siv);
     siv.run();
     Ok(())
@@ -101,22 +150,45 @@ fn custom_theme_from_cursive(siv: &Cursive) -> Theme {
 
 pub struct Config {
     debug: bool,
+    // Use vi key bindings in the command line.
+    vi: bool,
 }
 
 impl Config {
     pub fn new(matches: &ArgMatches) -> Result<Config, &'static str> {
         let debug = matches.occurrences_of("debug") > 0;
-        Ok(Config { debug })
+        let vi = matches.occurrences_of("vi") > 0;
+        Ok(Config { debug, vi })
     }
 }
 
 pub struct RunState {
-    current_dir: PathBuf,
+    // Commands currently running.
+    jobs: Jobs,
+    // Listing options for the session.
+    ls: LsOptions,
+    // Options and paths of the last listing, listed again when its files or
+    // the options change.
+    listed: Option<(LsOptions, Vec<String>)>,
+    // Watches the listed directories to refresh the listing, created with
+    // the first listing.
+    watcher: Option<DirectoryWatcher>,
+    // Incremented for each preview, to only show the last one requested.
+    preview_generation: usize,
+    // Path and modification time of the last file previewed, to preview it
+    // again only when it changed.
+    previewed: Option<(String, Option<SystemTime>)>,
 }
 
 impl RunState {
     fn new() -> RunState {
-        let current_dir: PathBuf = env::current_dir().unwrap();
-        RunState { current_dir }
+        RunState {
+            jobs: Jobs::new(),
+            ls: LsOptions::default(),
+            listed: None,
+            watcher: None,
+            preview_generation: 0,
+            previewed: None,
+        }
     }
 }
@@ -0,0 +1,661 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod ls {
+    use crate::command::run::FileEntry;
+    use crate::file::filetype::FileType;
+    use glob::MatchOptions;
+    use std::cmp::Ordering;
+    use std::collections::HashMap;
+    use std::ffi::CStr;
+    use std::fmt;
+    use std::iter::Peekable;
+    use std::os::unix::fs::MetadataExt;
+    use std::str::Chars;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use std::{mem, ptr};
+    use unicode_width::UnicodeWidthStr;
+
+    // Files modified longer ago than this show their year instead of
+    // their time, like ls does.
+    const RECENT_SECONDS: i64 = 6 * 30 * 24 * 3600;
+
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    // How files are listed, kept for the session and overridden by the
+    // ls flags.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct LsOptions {
+        // Show permissions, owner, size and modification time.
+        pub long: bool,
+        // Show the files starting with a dot.
+        pub show_hidden: bool,
+        pub sort: SortMode,
+        pub reverse: bool,
+        pub directories_first: bool,
+        // List . and .. in each directory, with -a.
+        pub dot_entries: bool,
+        // List the subdirectories too, with -R.
+        pub recursive: bool,
+        // List the directories themselves instead of their files, with -d.
+        pub directories: bool,
+    }
+
+    impl Default for LsOptions {
+        fn default() -> Self {
+            LsOptions {
+                long: false,
+                show_hidden: false,
+                sort: SortMode::Name,
+                reverse: false,
+                directories_first: true,
+                dot_entries: false,
+                recursive: false,
+                directories: false,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum SortMode {
+        // Natural order of the names, ignoring case.
+        Name,
+        // Largest first.
+        Size,
+        // Most recently modified first.
+        Time,
+        Extension,
+    }
+
+    impl SortMode {
+        /// Mode used after this one when cycling through the modes.
+        pub fn next(self) -> SortMode {
+            match self {
+                SortMode::Name => SortMode::Size,
+                SortMode::Size => SortMode::Time,
+                SortMode::Time => SortMode::Extension,
+                SortMode::Extension => SortMode::Name,
+            }
+        }
+    }
+
+    impl fmt::Display for SortMode {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                SortMode::Name => write!(f, "name"),
+                SortMode::Size => write!(f, "size"),
+                SortMode::Time => write!(f, "time"),
+                SortMode::Extension => write!(f, "extension"),
+            }
+        }
+    }
+
+    /// Reads the ls flags, returning the options and the paths to list.
+    /// Flags can be combined, as in -lah.
+    pub fn parse_args<'a>(
+        args: &[&'a str],
+        defaults: &LsOptions,
+    ) -> Result<(LsOptions, Vec<&'a str>), String> {
+        let mut options = defaults.clone();
+        let mut paths = Vec::new();
+        let mut end_of_options = false;
+        for arg in args {
+            match *arg {
+                "--" if !end_of_options => end_of_options = true,
+                flag if !end_of_options && flag.starts_with("--") => {
+                    return Err(format!("Unsupported option {}", flag));
+                }
+                flags if !end_of_options && flags.starts_with('-') && flags.len() > 1 => {
+                    for flag in flags[1..].chars() {
+                        match flag {
+                            'a' => {
+                                options.show_hidden = true;
+                                options.dot_entries = true;
+                            }
+                            'A' => {
+                                options.show_hidden = true;
+                                options.dot_entries = false;
+                            }
+                            'l' => options.long = true,
+                            // Sizes are always human readable, and files
+                            // listed one per line.
+                            'h' | '1' => (),
+                            't' => options.sort = SortMode::Time,
+                            'S' => options.sort = SortMode::Size,
+                            'X' => options.sort = SortMode::Extension,
+                            'r' => options.reverse = true,
+                            'R' => options.recursive = true,
+                            'd' => options.directories = true,
+                            _ => return Err(format!("Unsupported option -{}", flag)),
+                        }
+                    }
+                }
+                path => paths.push(path),
+            }
+        }
+        Ok((options, paths))
+    }
+
+    /// Expands the paths containing wildcards to the matching paths, like
+    /// a shell would. Patterns matching nothing are kept as they are.
+    pub fn expand_globs(paths: &[&str]) -> Vec<String> {
+        let match_options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: true,
+        };
+        let mut expanded = Vec::new();
+        for path in paths {
+            if !path.contains(|ch| "*?[".contains(ch)) {
+                expanded.push(path.to_string());
+                continue;
+            }
+            let mut matches: Vec<String> = match glob::glob_with(path, match_options) {
+                Ok(matches) => matches
+                    .filter_map(Result::ok)
+                    .filter_map(|matched| matched.to_str().map(String::from))
+                    .collect(),
+                Err(error) => {
+                    log::debug!("Invalid pattern {}: {:?}", path, error);
+                    Vec::new()
+                }
+            };
+            if matches.is_empty() {
+                expanded.push(path.to_string());
+            } else {
+                matches.sort_by(|a, b| compare_names(a, b));
+                expanded.append(&mut matches);
+            }
+        }
+        expanded
+    }
+
+    /// Sorts the entries following the options. Directories stay first
+    /// when reversing.
+    pub fn sort(entries: &mut [FileEntry], options: &LsOptions) {
+        entries.sort_by(|a, b| {
+            let directories = match options.directories_first {
+                true => is_directory(b).cmp(&is_directory(a)),
+                false => Ordering::Equal,
+            };
+            let order = match options.sort {
+                SortMode::Name => Ordering::Equal,
+                SortMode::Size => size(b).cmp(&size(a)),
+                SortMode::Time => modified(b).cmp(&modified(a)),
+                SortMode::Extension => compare_names(extension(a), extension(b)),
+            }
+            .then_with(|| a.cmp(b));
+            directories.then(match options.reverse {
+                true => order.reverse(),
+                false => order,
+            })
+        });
+    }
+
+    fn is_directory(entry: &FileEntry) -> bool {
+        matches!(entry.filetype, FileType::Directory)
+    }
+
+    fn size(entry: &FileEntry) -> u64 {
+        entry
+            .metadata
+            .as_ref()
+            .map(|metadata| metadata.size())
+            .unwrap_or(0)
+    }
+
+    fn modified(entry: &FileEntry) -> (i64, i64) {
+        entry
+            .metadata
+            .as_ref()
+            .map(|metadata| (metadata.mtime(), metadata.mtime_nsec()))
+            .unwrap_or((0, 0))
+    }
+
+    // Extension of the file name, empty for names starting with a dot and
+    // without another one.
+    fn extension(entry: &FileEntry) -> &str {
+        match entry.filename.rfind('.') {
+            Some(index) if index > 0 => &entry.filename[index + 1..],
+            _ => "",
+        }
+    }
+
+    /// Compares file names in natural order: ignoring case, and comparing
+    /// numbers by value so file2 comes before file10.
+    pub fn compare_names(a: &str, b: &str) -> Ordering {
+        let mut a_chars = a.chars().peekable();
+        let mut b_chars = b.chars().peekable();
+        loop {
+            let order = match (a_chars.peek(), b_chars.peek()) {
+                (None, None) => break,
+                (None, Some(_)) => Ordering::Less,
+                (Some(_), None) => Ordering::Greater,
+                (Some(a_char), Some(b_char))
+                    if a_char.is_ascii_digit() && b_char.is_ascii_digit() =>
+                {
+                    let a_number = take_number(&mut a_chars);
+                    let b_number = take_number(&mut b_chars);
+                    a_number
+                        .len()
+                        .cmp(&b_number.len())
+                        .then_with(|| a_number.cmp(&b_number))
+                }
+                (Some(a_char), Some(b_char)) => {
+                    let order = a_char.to_lowercase().cmp(b_char.to_lowercase());
+                    a_chars.next();
+                    b_chars.next();
+                    order
+                }
+            };
+            if order != Ordering::Equal {
+                return order;
+            }
+        }
+        // Names only differing by case or leading zeros still get an order.
+        a.cmp(b)
+    }
+
+    // Digits of the number at the start of the chars, without leading zeros.
+    fn take_number(chars: &mut Peekable<Chars>) -> String {
+        let mut number = String::new();
+        while let Some(ch) = chars.peek() {
+            if !ch.is_ascii_digit() {
+                break;
+            }
+            if !(number.is_empty() && *ch == '0') {
+                number.push(*ch);
+            }
+            chars.next();
+        }
+        number
+    }
+
+    /// Columns shown before each file name in the long listing, aligned.
+    pub fn long_columns(entries: &[FileEntry]) -> Vec<String> {
+        let now = now();
+        let mut users = HashMap::new();
+        let mut groups = HashMap::new();
+        let rows: Vec<[String; 6]> = entries
+            .iter()
+            .map(|entry| match &entry.metadata {
+                Some(metadata) => [
+                    mode_string(metadata.mode()),
+                    metadata.nlink().to_string(),
+                    users
+                        .entry(metadata.uid())
+                        .or_insert_with(|| user_name(metadata.uid()))
+                        .clone(),
+                    groups
+                        .entry(metadata.gid())
+                        .or_insert_with(|| group_name(metadata.gid()))
+                        .clone(),
+                    human_size(metadata.size()),
+                    format_time(metadata.mtime(), now),
+                ],
+                None => Default::default(),
+            })
+            .collect();
+        let mut widths = [0; 6];
+        for row in &rows {
+            for (width, column) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(column.width());
+            }
+        }
+        rows.iter()
+            .map(|row| {
+                // Numbers are aligned right, text left.
+                let columns: Vec<String> = row
+                    .iter()
+                    .zip(widths.iter())
+                    .enumerate()
+                    .map(|(index, (column, width))| match index {
+                        1 | 4 => pad_left(column, *width),
+                        _ => pad_right(column, *width),
+                    })
+                    .collect();
+                format!("{} ", columns.join(" "))
+            })
+            .collect()
+    }
+
+    fn pad_left(text: &str, width: usize) -> String {
+        format!("{}{}", " ".repeat(width.saturating_sub(text.width())), text)
+    }
+
+    fn pad_right(text: &str, width: usize) -> String {
+        format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())))
+    }
+
+    /// Type and permissions of the file, as in drwxr-xr-x.
+    pub fn mode_string(mode: u32) -> String {
+        let file_type = match mode & libc::S_IFMT {
+            libc::S_IFDIR => 'd',
+            libc::S_IFLNK => 'l',
+            libc::S_IFCHR => 'c',
+            libc::S_IFBLK => 'b',
+            libc::S_IFIFO => 'p',
+            libc::S_IFSOCK => 's',
+            _ => '-',
+        };
+        let mut result = String::with_capacity(10);
+        result.push(file_type);
+        // Read, write and execute for user, group and others, the execute
+        // bit being replaced by setuid, setgid and sticky bits when set.
+        let special = [
+            (libc::S_ISUID, 's'),
+            (libc::S_ISGID, 's'),
+            (libc::S_ISVTX, 't'),
+        ];
+        for (index, (special_bit, special_char)) in special.iter().enumerate() {
+            let bits = (mode >> (6 - 3 * index)) & 0o7;
+            result.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+            result.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+            let execute = bits & 0o1 != 0;
+            result.push(match (mode & special_bit != 0, execute) {
+                (true, true) => *special_char,
+                (true, false) => special_char.to_ascii_uppercase(),
+                (false, true) => 'x',
+                (false, false) => '-',
+            });
+        }
+        result
+    }
+
+    /// Size with a unit, as in 1.5K.
+    pub fn human_size(size: u64) -> String {
+        const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+        if size < 1024 {
+            return size.to_string();
+        }
+        let mut value = size as f64;
+        let mut unit = "";
+        // Compared once rounded as shown, so 1048575 is 1.0M and not 1024K.
+        for next_unit in UNITS.iter() {
+            if value.round() < 1024.0 {
+                break;
+            }
+            value /= 1024.0;
+            unit = next_unit;
+        }
+        match (value * 10.0).round() < 100.0 {
+            true => format!("{:.1}{}", value, unit),
+            false => format!("{:.0}{}", value, unit),
+        }
+    }
+
+    /// Modification time as shown by the long listing.
+    pub fn modification_time(time: i64) -> String {
+        format_time(time, now())
+    }
+
+    fn now() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(0)
+    }
+
+    fn format_time(time: i64, now: i64) -> String {
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        let time_t = time as libc::time_t;
+        if unsafe { libc::localtime_r(&time_t, &mut tm) }.is_null() {
+            return String::from("?");
+        }
+        let recent = time <= now && now - time < RECENT_SECONDS;
+        format_date(&tm, recent)
+    }
+
+    fn format_date(tm: &libc::tm, recent: bool) -> String {
+        let month = MONTHS.get(tm.tm_mon as usize).unwrap_or(&"???");
+        match recent {
+            true => format!(
+                "{} {:>2} {:02}:{:02}",
+                month, tm.tm_mday, tm.tm_hour, tm.tm_min
+            ),
+            false => format!("{} {:>2}  {}", month, tm.tm_mday, tm.tm_year + 1900),
+        }
+    }
+
+    // Size of the buffer for the user and group database entries.
+    const NAME_BUFFER_SIZE: usize = 16 * 1024;
+
+    /// Name of the user, or its id if unknown.
+    pub fn user_name(uid: u32) -> String {
+        let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+        let mut buffer = vec![0 as libc::c_char; NAME_BUFFER_SIZE];
+        let mut result = ptr::null_mut();
+        let status = unsafe {
+            libc::getpwuid_r(
+                uid,
+                &mut passwd,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        if status != 0 || result.is_null() {
+            return uid.to_string();
+        }
+        unsafe { CStr::from_ptr(passwd.pw_name) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Name of the group, or its id if unknown.
+    pub fn group_name(gid: u32) -> String {
+        let mut group: libc::group = unsafe { mem::zeroed() };
+        let mut buffer = vec![0 as libc::c_char; NAME_BUFFER_SIZE];
+        let mut result = ptr::null_mut();
+        let status = unsafe {
+            libc::getgrgid_r(
+                gid,
+                &mut group,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        if status != 0 || result.is_null() {
+            return gid.to_string();
+        }
+        unsafe { CStr::from_ptr(group.gr_name) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::{env, fs, process};
+
+        #[test]
+        fn test_parse_args() {
+            let defaults = LsOptions::default();
+            let (options, paths) = parse_args(&["-l", "src"], &defaults).unwrap();
+            assert!(options.long);
+            assert!(!options.show_hidden);
+            assert_eq!(paths, ["src"]);
+            let (options, _) = parse_args(&["-a"], &defaults).unwrap();
+            assert!(options.show_hidden);
+            assert!(options.dot_entries);
+            let (options, paths) = parse_args(&["-lAhSr1", "a", "-R", "b"], &defaults).unwrap();
+            assert!(options.long && options.show_hidden && !options.dot_entries);
+            assert!(options.reverse && options.recursive && !options.directories);
+            assert_eq!(options.sort, SortMode::Size);
+            assert_eq!(paths, ["a", "b"]);
+            let (options, paths) = parse_args(&["-d", "--", "-l", "-"], &defaults).unwrap();
+            assert!(options.directories && !options.long);
+            assert_eq!(paths, ["-l", "-"]);
+            assert_eq!(
+                parse_args(&["-lZ"], &defaults),
+                Err(String::from("Unsupported option -Z"))
+            );
+            assert!(parse_args(&["--color"], &defaults).is_err());
+        }
+
+        #[test]
+        fn test_expand_globs() {
+            let dir = env::temp_dir().join(format!("manette-test-expand-globs-{}", process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            for name in ["ls.rs", "lib.rs", "main.rs", ".l.rs", "lexer.rs"] {
+                fs::write(dir.join(name), "").unwrap();
+            }
+            let dir_name = dir.display().to_string();
+            let pattern = format!("{}/l*.rs", dir_name);
+            let expanded = expand_globs(&[&pattern, "README.md", "*.nothing"]);
+            fs::remove_dir_all(&dir).unwrap();
+            let expected: Vec<String> = ["lexer.rs", "lib.rs", "ls.rs"]
+                .iter()
+                .map(|name| format!("{}/{}", dir_name, name))
+                .chain(
+                    ["README.md", "*.nothing"]
+                        .iter()
+                        .map(|name| name.to_string()),
+                )
+                .collect();
+            assert_eq!(expanded, expected);
+        }
+
+        fn entry(filename: &str, filetype: FileType) -> FileEntry {
+            FileEntry {
+                filename: filename.to_string(),
+                filetype,
+                metadata: None,
+            }
+        }
+
+        fn sorted(options: &LsOptions) -> Vec<String> {
+            let mut entries = vec![
+                entry("file10.txt", FileType::File),
+                entry("File2.rs", FileType::File),
+                entry("src", FileType::Directory),
+                entry("file1.txt", FileType::File),
+                entry("Docs", FileType::Directory),
+                entry(".hidden", FileType::File),
+            ];
+            sort(&mut entries, options);
+            entries.into_iter().map(|entry| entry.filename).collect()
+        }
+
+        #[test]
+        fn test_compare_names() {
+            assert_eq!(compare_names("file2", "file10"), Ordering::Less);
+            assert_eq!(compare_names("File2", "file10"), Ordering::Less);
+            assert_eq!(compare_names("a", "B"), Ordering::Less);
+            assert_eq!(compare_names("v1.02", "v1.2"), Ordering::Less);
+            assert_eq!(compare_names("abc", "abc"), Ordering::Equal);
+            assert_eq!(compare_names("ab", "abc"), Ordering::Less);
+        }
+
+        #[test]
+        fn test_sort() {
+            let mut options = LsOptions::default();
+            let expected = [
+                "Docs",
+                "src",
+                ".hidden",
+                "file1.txt",
+                "File2.rs",
+                "file10.txt",
+            ];
+            assert_eq!(sorted(&options), expected);
+            options.reverse = true;
+            let expected = [
+                "src",
+                "Docs",
+                "file10.txt",
+                "File2.rs",
+                "file1.txt",
+                ".hidden",
+            ];
+            assert_eq!(sorted(&options), expected);
+            options.reverse = false;
+            options.directories_first = false;
+            options.sort = SortMode::Extension;
+            let expected = [
+                ".hidden",
+                "Docs",
+                "src",
+                "File2.rs",
+                "file1.txt",
+                "file10.txt",
+            ];
+            assert_eq!(sorted(&options), expected);
+        }
+
+        #[test]
+        fn test_mode_string() {
+            assert_eq!(mode_string(libc::S_IFDIR | 0o755), "drwxr-xr-x");
+            assert_eq!(mode_string(libc::S_IFREG | 0o644), "-rw-r--r--");
+            assert_eq!(mode_string(libc::S_IFREG | 0o4755), "-rwsr-xr-x");
+            assert_eq!(mode_string(libc::S_IFDIR | 0o1777), "drwxrwxrwt");
+            assert_eq!(mode_string(libc::S_IFLNK | 0o2644), "lrw-r-Sr--");
+        }
+
+        #[test]
+        fn test_human_size() {
+            assert_eq!(human_size(0), "0");
+            assert_eq!(human_size(1023), "1023");
+            assert_eq!(human_size(1536), "1.5K");
+            assert_eq!(human_size(10239), "10K");
+            assert_eq!(human_size(1048575), "1.0M");
+            assert_eq!(human_size(20 * 1024 * 1024), "20M");
+            assert_eq!(human_size(3 * 1024 * 1024 * 1024), "3.0G");
+        }
+
+        #[test]
+        fn test_format_date() {
+            let mut tm: libc::tm = unsafe { mem::zeroed() };
+            tm.tm_year = 122;
+            tm.tm_mon = 2;
+            tm.tm_mday = 7;
+            tm.tm_hour = 9;
+            tm.tm_min = 5;
+            assert_eq!(format_date(&tm, true), "Mar  7 09:05");
+            assert_eq!(format_date(&tm, false), "Mar  7  2022");
+        }
+
+        #[test]
+        fn test_names() {
+            assert_eq!(user_name(0), "root");
+            assert_eq!(group_name(0), "root");
+            assert_eq!(user_name(u32::MAX - 1), (u32::MAX - 1).to_string());
+        }
+    }
+}
@@ -50,6 +50,11 @@ fn main() {
                 .short("d")
                 .help("Enable debug"),
         )
+        .arg(
+            Arg::with_name("vi")
+                .long("vi")
+                .help("Use vi key bindings in the command line"),
+        )
         .get_matches();
 
     let config = Config::new(&matches).unwrap_or_else(|err| {
@@ -0,0 +1,262 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod opener {
+    use crate::file::filekind::FileKind;
+    use crate::lexer::lexer;
+    use crate::userenv::userenv;
+    use glob::Pattern;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+
+    // Openers used when no user opener matches. Text files are shown in
+    // the builtin viewer, other files with the desktop application in a
+    // graphical session and in the builtin hex viewer otherwise.
+    const DEFAULT_OPENERS: &str = "\
+text/* inline view
+inode/x-empty inline view
+";
+    const DESKTOP_OPENER: &str = "* detached xdg-open";
+    const TERMINAL_OPENER: &str = "* inline hexview";
+
+    // Placeholder replaced by the file name in opener commands.
+    const FILE_PLACEHOLDER: &str = "%f";
+
+    // How the program opening the file is run.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum OpenMode {
+        // With the full terminal, the UI being stopped until it exits.
+        Detached,
+        // In the embedded terminal, for interactive programs.
+        Pty,
+        // As a command, its output shown in the command output.
+        Inline,
+    }
+
+    impl FromStr for OpenMode {
+        type Err = String;
+
+        fn from_str(mode: &str) -> Result<Self, Self::Err> {
+            match mode {
+                "detached" => Ok(OpenMode::Detached),
+                "pty" => Ok(OpenMode::Pty),
+                "inline" => Ok(OpenMode::Inline),
+                _ => Err(format!("Unknown open mode {}", mode)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct Opener {
+        // Matched against the MIME type when it contains a slash, like
+        // image/*, against the file name otherwise, like *.pdf.
+        pattern: Pattern,
+        matches_mime_type: bool,
+        pub mode: OpenMode,
+        command: String,
+    }
+
+    impl Opener {
+        /// Reads an opener from a line of the openers file, made of the
+        /// pattern, the mode and the command.
+        pub fn parse(line: &str) -> Result<Opener, String> {
+            let (pattern, fields) = split_field(line.trim());
+            let (mode, command) = split_field(fields);
+            if command.is_empty() {
+                return Err(format!(
+                    "Expected a pattern, a mode and a command: {}",
+                    line
+                ));
+            }
+            Ok(Opener {
+                pattern: Pattern::new(pattern)
+                    .map_err(|error| format!("Invalid pattern {}: {}", pattern, error))?,
+                matches_mime_type: pattern.contains('/'),
+                mode: mode.parse()?,
+                command: command.to_string(),
+            })
+        }
+
+        /// Tells whether the opener opens the file. Text files are text/plain
+        /// too, whatever their MIME type.
+        pub fn matches(&self, filename: &str, kind: &FileKind) -> bool {
+            match self.matches_mime_type {
+                true => {
+                    self.pattern.matches(&kind.mime_type)
+                        || (kind.is_text() && self.pattern.matches("text/plain"))
+                }
+                false => {
+                    let name = Path::new(filename)
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .unwrap_or(filename);
+                    self.pattern.matches(name)
+                }
+            }
+        }
+
+        /// Command opening the file, which replaces %f in the opener
+        /// command or follows it.
+        pub fn command(&self, filename: &str) -> String {
+            let filename = lexer::quote(filename);
+            match self.command.contains(FILE_PLACEHOLDER) {
+                true => self.command.replace(FILE_PLACEHOLDER, &filename),
+                false => format!("{} {}", self.command, filename),
+            }
+        }
+
+        /// Program run by the opener.
+        pub fn program(&self) -> &str {
+            self.command.split_whitespace().next().unwrap_or("")
+        }
+    }
+
+    /// Openers tried in order to open a file, the first matching one being
+    /// used.
+    pub struct Openers {
+        openers: Vec<Opener>,
+    }
+
+    impl Openers {
+        /// Loads the user openers, followed by the default ones.
+        pub fn load_default() -> Self {
+            let path = openers_path();
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(error) => {
+                    log::debug!("Cannot read openers {:?}: {:?}", path, error);
+                    String::new()
+                }
+            };
+            Openers::parse(&format!(
+                "{}\n{}",
+                content,
+                default_openers(userenv::has_display())
+            ))
+        }
+
+        /// Reads the openers, one per line. Empty lines and lines starting
+        /// with # are ignored.
+        pub fn parse(content: &str) -> Self {
+            let openers = content
+                .lines()
+                .filter(|line| !(line.trim().is_empty() || line.trim_start().starts_with('#')))
+                .filter_map(|line| match Opener::parse(line) {
+                    Ok(opener) => Some(opener),
+                    Err(error) => {
+                        log::error!("Cannot read opener: {}", error);
+                        None
+                    }
+                })
+                .collect();
+            Openers { openers }
+        }
+
+        pub fn find(&self, filename: &str, kind: &FileKind) -> Option<&Opener> {
+            self.openers
+                .iter()
+                .find(|opener| opener.matches(filename, kind))
+        }
+    }
+
+    fn default_openers(has_display: bool) -> String {
+        let fallback = match has_display {
+            true => DESKTOP_OPENER,
+            false => TERMINAL_OPENER,
+        };
+        format!("{}{}\n", DEFAULT_OPENERS, fallback)
+    }
+
+    // Splits the first field from the rest of the text.
+    fn split_field(text: &str) -> (&str, &str) {
+        match text.find(char::is_whitespace) {
+            Some(index) => (&text[..index], text[index..].trim_start()),
+            None => (text, ""),
+        }
+    }
+
+    fn openers_path() -> PathBuf {
+        userenv::config_dir().join("manette").join("openers")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::file::filekind;
+
+        #[test]
+        fn test_parse() {
+            let opener = Opener::parse("image/*  pty  timg -g 80x40").unwrap();
+            assert_eq!(opener.mode, OpenMode::Pty);
+            assert_eq!(opener.program(), "timg");
+            assert_eq!(opener.command("a b.png"), "timg -g 80x40 a\\ b.png");
+            let opener = Opener::parse("*.pdf detached zathura %f &").unwrap();
+            assert_eq!(opener.command("doc.pdf"), "zathura doc.pdf &");
+            assert!(Opener::parse("*.pdf detached").is_err());
+            assert!(Opener::parse("*.pdf background zathura").is_err());
+        }
+
+        #[test]
+        fn test_find() {
+            let openers = Openers::parse(&format!(
+                "# Documents\n*.pdf detached zathura\n\nimage/* pty timg\n{}",
+                default_openers(false)
+            ));
+            let program = |filename, content: &[u8]| {
+                let kind = filekind::from_content(Path::new(filename), content);
+                openers
+                    .find(filename, &kind)
+                    .map(|opener| opener.program().to_string())
+            };
+            assert_eq!(program("docs/a.pdf", b"%PDF-1.4").unwrap(), "zathura");
+            assert_eq!(program("a.png", b"\x89PNG\r\n\x1a\n").unwrap(), "timg");
+            assert_eq!(program("main.rs", b"fn main() {}\n").unwrap(), "view");
+            // Text files of other MIME types are text/plain too.
+            assert_eq!(program("data.json", b"{}\n").unwrap(), "view");
+            assert_eq!(program("a.zip", b"PK\x03\x04").unwrap(), "hexview");
+            assert_eq!(program("empty", b"").unwrap(), "view");
+            let kind = filekind::from_content(Path::new("a"), b"text\n");
+            assert!(Openers::parse("").find("a", &kind).is_none());
+
+            let openers = Openers::parse(&default_openers(true));
+            let kind = filekind::from_content(Path::new("a.zip"), b"PK\x03\x04");
+            assert_eq!(openers.find("a.zip", &kind).unwrap().program(), "xdg-open");
+            let kind = filekind::from_content(Path::new("a.txt"), b"text\n");
+            assert_eq!(openers.find("a.txt", &kind).unwrap().program(), "view");
+        }
+    }
+}
@@ -0,0 +1,246 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod preview {
+    use crate::file::filekind;
+    use crate::ls::ls;
+    use std::fs::{self, File, Metadata};
+    use std::io::{self, Read};
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    use std::path::Path;
+
+    // Only the start of the files is read, so large files are previewed
+    // as fast as small ones.
+    const PREVIEW_BYTES: u64 = 64 * 1024;
+    const PREVIEW_LINES: usize = 500;
+    // Bytes shown in the hex dump of binary files.
+    const HEX_DUMP_BYTES: usize = 4096;
+    const HEX_ROW_BYTES: usize = 16;
+
+    /// Preview of the file: its lines when it is text, its files when it
+    /// is a directory, its type and a hex dump when it is binary and its
+    /// metadata otherwise. Files starting with a dot are only listed when
+    /// showing hidden files.
+    pub fn preview(path: &Path, show_hidden: bool) -> String {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(error) => return format!("Cannot read {}: {}", path.display(), error),
+        };
+        if metadata.is_dir() {
+            return match directory_preview(path, show_hidden) {
+                Ok(preview) => preview,
+                Err(error) => format!("Cannot list {}: {}", path.display(), error),
+            };
+        }
+        // Reading devices or pipes could block or never end.
+        if !metadata.is_file() || metadata.len() == 0 {
+            return metadata_preview(path, &metadata);
+        }
+        match read_start(path) {
+            Ok(content) => match filekind::from_content(path, &content) {
+                kind if kind.is_text() => {
+                    text_preview(filekind::decode_text(&content).unwrap_or(""))
+                }
+                kind => format!(
+                    "{}\n\n{}",
+                    kind.mime_type,
+                    hex_dump(&content[..content.len().min(HEX_DUMP_BYTES)], 0)
+                ),
+            },
+            Err(error) => format!(
+                "Cannot read {}: {}\n\n{}",
+                path.display(),
+                error,
+                metadata_preview(path, &metadata)
+            ),
+        }
+    }
+
+    fn read_start(path: &Path) -> io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        File::open(path)?
+            .take(PREVIEW_BYTES)
+            .read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    /// Lines of the text with their number.
+    pub fn text_preview(text: &str) -> String {
+        let lines: Vec<&str> = text.lines().take(PREVIEW_LINES).collect();
+        let width = lines.len().to_string().len();
+        lines
+            .iter()
+            .enumerate()
+            .map(|(index, line)| {
+                format!(
+                    "{:>width$} {}\n",
+                    index + 1,
+                    line.replace('\t', "    "),
+                    width = width
+                )
+            })
+            .collect()
+    }
+
+    /// Offsets, bytes and printable characters of the content, 16 bytes
+    /// per row.
+    pub fn hex_dump(content: &[u8], offset: usize) -> String {
+        content
+            .chunks(HEX_ROW_BYTES)
+            .enumerate()
+            .map(|(index, row)| {
+                let bytes: Vec<String> = row.iter().map(|byte| format!("{:02x}", byte)).collect();
+                let characters: String = row
+                    .iter()
+                    .map(|byte| match byte.is_ascii_graphic() || *byte == b' ' {
+                        true => *byte as char,
+                        false => '.',
+                    })
+                    .collect();
+                format!(
+                    "{:08x}  {:<width$}  {}\n",
+                    offset + index * HEX_ROW_BYTES,
+                    bytes.join(" "),
+                    characters,
+                    width = HEX_ROW_BYTES * 3 - 1
+                )
+            })
+            .collect()
+    }
+
+    // Files of the directory, in natural order with directories first.
+    fn directory_preview(path: &Path, show_hidden: bool) -> io::Result<String> {
+        let mut names: Vec<(bool, String)> = fs::read_dir(path)?
+            .filter_map(Result::ok)
+            .filter(|entry| show_hidden || !entry.file_name().to_string_lossy().starts_with('.'))
+            .map(|entry| {
+                let is_dir = entry.path().is_dir();
+                let mut name = entry.file_name().to_string_lossy().into_owned();
+                if is_dir {
+                    name.push('/');
+                }
+                (is_dir, name)
+            })
+            .collect();
+        if names.is_empty() {
+            return Ok(String::from("Empty directory"));
+        }
+        names.sort_by(|(a_is_dir, a), (b_is_dir, b)| {
+            b_is_dir.cmp(a_is_dir).then_with(|| ls::compare_names(a, b))
+        });
+        let mut preview: String = names
+            .iter()
+            .take(PREVIEW_LINES)
+            .map(|(_, name)| format!("{}\n", name))
+            .collect();
+        if names.len() > PREVIEW_LINES {
+            preview.push_str(&format!("… {} more\n", names.len() - PREVIEW_LINES));
+        }
+        Ok(preview)
+    }
+
+    fn metadata_preview(path: &Path, metadata: &Metadata) -> String {
+        // Links are described themselves rather than their target.
+        let link_metadata = fs::symlink_metadata(path).unwrap_or_else(|_| metadata.clone());
+        let file_type = link_metadata.file_type();
+        let description = if file_type.is_symlink() {
+            match fs::read_link(path) {
+                Ok(target) => format!("symbolic link to {}", target.display()),
+                Err(_) => String::from("symbolic link"),
+            }
+        } else if file_type.is_char_device() {
+            String::from("character device")
+        } else if file_type.is_block_device() {
+            String::from("block device")
+        } else if file_type.is_fifo() {
+            String::from("named pipe")
+        } else if file_type.is_socket() {
+            String::from("socket")
+        } else if metadata.len() == 0 {
+            String::from("empty file")
+        } else {
+            String::from("file")
+        };
+        format!(
+            "Type: {}\nSize: {} ({} bytes)\nPermissions: {}\nOwner: {}:{}\nModified: {}\n",
+            description,
+            ls::human_size(metadata.size()),
+            metadata.size(),
+            ls::mode_string(link_metadata.mode()),
+            ls::user_name(metadata.uid()),
+            ls::group_name(metadata.gid()),
+            ls::modification_time(metadata.mtime())
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_text_preview() {
+            let text: String = (1..=10).map(|line| format!("line {}\n", line)).collect();
+            let preview = text_preview(&text);
+            assert!(preview.starts_with(" 1 line 1\n 2 line 2\n"));
+            assert!(preview.ends_with("10 line 10\n"));
+            assert_eq!(text_preview("\tindented"), "1     indented\n");
+        }
+
+        #[test]
+        fn test_hex_dump() {
+            let content: Vec<u8> = (0x3e..0x50).collect();
+            assert_eq!(
+                hex_dump(&content, 0x100),
+                "00000100  3e 3f 40 41 42 43 44 45 46 47 48 49 4a 4b 4c 4d  >?@ABCDEFGHIJKLM\n\
+                 00000110  4e 4f                                            NO\n"
+            );
+            assert_eq!(
+                hex_dump(b"a\n", 0),
+                format!("00000000  61 0a{}  a.\n", " ".repeat(42))
+            );
+        }
+
+        #[test]
+        fn test_preview() {
+            assert!(preview(Path::new("src"), false).contains("preview.rs\n"));
+            assert!(!preview(Path::new("."), false).contains(".gitignore"));
+            assert!(preview(Path::new("."), true).contains(".gitignore\n"));
+            assert!(preview(Path::new("Cargo.toml"), false).starts_with(" 1 [package]\n"));
+            assert!(preview(Path::new("/dev/null"), false).starts_with("Type: character device\n"));
+            assert!(preview(Path::new("does-not-exist"), false).starts_with("Cannot read"));
+        }
+    }
+}
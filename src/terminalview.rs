@@ -0,0 +1,288 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+use anyhow::Result;
+use cursive::direction::Direction;
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::{BaseColor, Color, ColorStyle, Effect, Style};
+use cursive::{CbSink, Printer, Vec2, View};
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, ExitStatus, PtySize};
+use std::env;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Initial size of the terminal, until the view gets its layout.
+const DEFAULT_ROWS: u16 = 24;
+const DEFAULT_COLS: u16 = 80;
+
+// Emulates a terminal running a command in a pseudo-terminal, so
+// interactive programs can run inside the manette layout.
+pub struct TerminalView {
+    parser: Arc<Mutex<vt100::Parser>>,
+    master: Box<dyn portable_pty::MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    // Process id of the command, if known.
+    pid: Option<u32>,
+    size: Vec2,
+}
+
+impl TerminalView {
+    /// Starts the command in a new pseudo-terminal. The screen is redrawn
+    /// through the sink every time the command writes, and on_exit is called
+    /// with the process id and exit status once the command completes.
+    pub fn spawn<F>(command: &str, sink: CbSink, on_exit: F) -> Result<Self>
+    where
+        F: FnOnce(Option<u32>, ExitStatus) + Send + 'static,
+    {
+        let pair = native_pty_system().openpty(PtySize {
+            rows: DEFAULT_ROWS,
+            cols: DEFAULT_COLS,
+            pixel_width: 0,
+            pixel_height: 0,
+        })?;
+        let mut builder = CommandBuilder::new("/bin/sh");
+        builder.arg("-c");
+        builder.arg(command);
+        builder.cwd(env::current_dir()?);
+        builder.env("TERM", "xterm-256color");
+        let mut child = pair.slave.spawn_command(builder)?;
+        // Drop our end of the slave so we get EOF when the command exits.
+        drop(pair.slave);
+
+        let parser = Arc::new(Mutex::new(vt100::Parser::new(
+            DEFAULT_ROWS,
+            DEFAULT_COLS,
+            0,
+        )));
+        let mut reader = pair.master.try_clone_reader()?;
+        let reader_parser = Arc::clone(&parser);
+        thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(len) => {
+                        reader_parser.lock().unwrap().process(&buffer[..len]);
+                        // Wake up the UI to redraw the screen.
+                        if sink.send(Box::new(|_| ())).is_err() {
+                            break;
+                        }
+                    }
+                    Err(error) => {
+                        log::debug!("Terminal read ended: {:?}", error);
+                        break;
+                    }
+                }
+            }
+        });
+
+        let killer = child.clone_killer();
+        let pid = child.process_id();
+        thread::spawn(move || match child.wait() {
+            Ok(status) => on_exit(pid, status),
+            Err(error) => log::error!("Error waiting for terminal command: {:?}", error),
+        });
+
+        let writer = pair.master.take_writer()?;
+        Ok(TerminalView {
+            parser,
+            master: pair.master,
+            writer,
+            killer,
+            pid,
+            size: Vec2::new(DEFAULT_COLS as usize, DEFAULT_ROWS as usize),
+        })
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    fn send(&mut self, bytes: &[u8]) -> EventResult {
+        if let Err(error) = self.writer.write_all(bytes) {
+            log::error!("Cannot write to terminal: {:?}", error);
+        }
+        EventResult::Consumed(None)
+    }
+
+    // Translates a key press into the bytes a terminal would send.
+    fn key_bytes(&self, event: &Event) -> Option<Vec<u8>> {
+        let application_cursor = self.parser.lock().unwrap().screen().application_cursor();
+        let cursor = |code: &str| match application_cursor {
+            true => format!("\x1bO{}", code).into_bytes(),
+            false => format!("\x1b[{}", code).into_bytes(),
+        };
+        let bytes = match event {
+            Event::Char(ch) => ch.to_string().into_bytes(),
+            Event::AltChar(ch) => format!("\x1b{}", ch).into_bytes(),
+            Event::CtrlChar(ch) => vec![(*ch as u8) & 0x1f],
+            Event::Key(Key::Enter) => vec![b'\r'],
+            Event::Key(Key::Tab) => vec![b'\t'],
+            Event::Shift(Key::Tab) => b"\x1b[Z".to_vec(),
+            Event::Key(Key::Backspace) => vec![0x7f],
+            Event::Key(Key::Esc) => vec![0x1b],
+            Event::Key(Key::Up) => cursor("A"),
+            Event::Key(Key::Down) => cursor("B"),
+            Event::Key(Key::Right) => cursor("C"),
+            Event::Key(Key::Left) => cursor("D"),
+            Event::Key(Key::Home) => cursor("H"),
+            Event::Key(Key::End) => cursor("F"),
+            Event::Key(Key::Ins) => b"\x1b[2~".to_vec(),
+            Event::Key(Key::Del) => b"\x1b[3~".to_vec(),
+            Event::Key(Key::PageUp) => b"\x1b[5~".to_vec(),
+            Event::Key(Key::PageDown) => b"\x1b[6~".to_vec(),
+            Event::Key(Key::F1) => b"\x1bOP".to_vec(),
+            Event::Key(Key::F2) => b"\x1bOQ".to_vec(),
+            Event::Key(Key::F3) => b"\x1bOR".to_vec(),
+            Event::Key(Key::F4) => b"\x1bOS".to_vec(),
+            Event::Key(Key::F5) => b"\x1b[15~".to_vec(),
+            Event::Key(Key::F6) => b"\x1b[17~".to_vec(),
+            Event::Key(Key::F7) => b"\x1b[18~".to_vec(),
+            Event::Key(Key::F8) => b"\x1b[19~".to_vec(),
+            Event::Key(Key::F9) => b"\x1b[20~".to_vec(),
+            Event::Key(Key::F10) => b"\x1b[21~".to_vec(),
+            Event::Key(Key::F11) => b"\x1b[23~".to_vec(),
+            Event::Key(Key::F12) => b"\x1b[24~".to_vec(),
+            _ => return None,
+        };
+        Some(bytes)
+    }
+}
+
+fn convert_color(color: vt100::Color) -> Color {
+    match color {
+        vt100::Color::Default => Color::TerminalDefault,
+        vt100::Color::Idx(index) if index < 8 => Color::Dark(BaseColor::from(index)),
+        vt100::Color::Idx(index) if index < 16 => Color::Light(BaseColor::from(index - 8)),
+        vt100::Color::Idx(index) => Color::from_256colors(index),
+        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    }
+}
+
+fn cell_style(cell: &vt100::Cell) -> Style {
+    let mut style = Style::from(ColorStyle::new(
+        convert_color(cell.fgcolor()),
+        convert_color(cell.bgcolor()),
+    ));
+    if cell.bold() {
+        style.effects.insert(Effect::Bold);
+    }
+    if cell.italic() {
+        style.effects.insert(Effect::Italic);
+    }
+    if cell.underline() {
+        style.effects.insert(Effect::Underline);
+    }
+    if cell.inverse() {
+        style.effects.insert(Effect::Reverse);
+    }
+    style
+}
+
+impl View for TerminalView {
+    fn draw(&self, printer: &Printer) {
+        let parser = self.parser.lock().unwrap();
+        let screen = parser.screen();
+        let (rows, cols) = screen.size();
+        let (cursor_row, cursor_col) = screen.cursor_position();
+        let show_cursor = printer.focused && !screen.hide_cursor();
+        for row in 0..rows {
+            for col in 0..cols {
+                let cell = match screen.cell(row, col) {
+                    Some(cell) => cell,
+                    None => continue,
+                };
+                if cell.is_wide_continuation() {
+                    continue;
+                }
+                let mut style = cell_style(cell);
+                if show_cursor && row == cursor_row && col == cursor_col {
+                    // Draw the cursor by swapping the cell colors.
+                    if !style.effects.remove(Effect::Reverse) {
+                        style.effects.insert(Effect::Reverse);
+                    }
+                }
+                let contents = cell.contents();
+                let text = if contents.is_empty() { " " } else { &contents };
+                printer.with_style(style, |printer| {
+                    printer.print((col as usize, row as usize), text);
+                });
+            }
+        }
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        if size == self.size || size.x == 0 || size.y == 0 {
+            return;
+        }
+        self.size = size;
+        let rows = size.y.min(u16::MAX as usize) as u16;
+        let cols = size.x.min(u16::MAX as usize) as u16;
+        self.parser.lock().unwrap().set_size(rows, cols);
+        if let Err(error) = self.master.resize(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        }) {
+            log::error!("Cannot resize terminal: {:?}", error);
+        }
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        constraint
+    }
+
+    fn take_focus(&mut self, _source: Direction) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match self.key_bytes(&event) {
+            Some(bytes) => self.send(&bytes),
+            None => EventResult::Ignored,
+        }
+    }
+}
+
+impl Drop for TerminalView {
+    fn drop(&mut self) {
+        // Removing the view stops the command it runs, if still running.
+        let _ = self.killer.kill();
+    }
+}
@@ -39,62 +39,44 @@ pub mod update {
     use cursive::event::Event;
     use cursive::event::EventResult;
     use cursive::{
-        theme::{BaseColor, Color},
+        theme::{BaseColor, Color, Effect},
         utils::markup::StyledString,
-        views::{LinearLayout, OnEventView, SelectView},
+        views::{LinearLayout, OnEventView, Panel, SelectView},
     };
     use cursive::{
         traits::{Nameable, Scrollable},
-        views::{ResizedView, ScrollView, TextView},
+        view::{ScrollStrategy, View},
+        views::{NamedView, ResizedView, ScrollView, TextView},
         Cursive,
     };
 
-    use crate::command::run::{self, CommandResult, FileEntry};
+    use crate::command::job;
+    use crate::command::run::{self, CommandResult, Listing};
+    use crate::file::filekind::{self, Category};
     use crate::file::filetype::FileType;
+    use crate::ls::ls;
+    use crate::terminalview::TerminalView;
     use crate::view::CliView;
+    use crate::RunState;
+    use std::path::Path;
 
-    pub fn file_list_view(s: &mut Cursive, file_list: Vec<FileEntry>) {
+    type FileListView = ScrollView<OnEventView<SelectView<String>>>;
+    type PreviewScroll = ScrollView<NamedView<TextView>>;
+    type PreviewPanel = Panel<NamedView<PreviewScroll>>;
+
+    pub fn file_list_view(s: &mut Cursive, listings: Vec<Listing>, long: bool) {
+        detach_foreground(s);
+        let mut select = SelectView::new();
+        select.add_all(file_list_items(listings, long));
+        select.set_on_submit(|s, selection: &String| {
+            log::debug!("File list: {:?} selected", selection);
+            run::submit_file(s, selection);
+        });
+        select.set_on_select(|s, selection: &String| run::show_preview(s, selection));
         s.call_on_name("command_layout", |layout: &mut LinearLayout| {
             clear_output_layers(layout);
-            let mut select = SelectView::new();
-            select.add_all(
-                file_list
-                    .into_iter()
-                    .map(|file_entry| match file_entry.filetype {
-                        FileType::Directory => (
-                            StyledString::styled(
-                                file_entry.filename.clone(),
-                                Color::Light(BaseColor::Blue),
-                            ),
-                            file_entry.filename,
-                        ),
-                        FileType::Executable => (
-                            StyledString::styled(
-                                file_entry.filename.clone(),
-                                Color::Light(BaseColor::Green),
-                            ),
-                            file_entry.filename,
-                        ),
-                        FileType::Symlink => (
-                            StyledString::styled(
-                                file_entry.filename.clone(),
-                                Color::Dark(BaseColor::Blue),
-                            ),
-                            file_entry.filename,
-                        ),
-                        _ => (
-                            StyledString::plain(file_entry.filename.clone()),
-                            file_entry.filename,
-                        ),
-                    }),
-            );
-            select.set_on_submit(|s, selection: &String| {
-                log::debug!("File list: {:?} selected", selection);
-                run::submit_file(s, selection);
-            });
-
-            let on_event =
-                OnEventView::new(select).on_event_inner('e', |sel: &mut SelectView, _e: &Event| {
+            let on_event = OnEventView::new(select)
+                .on_event_inner('e', |sel: &mut SelectView, _e: &Event| {
                     log::debug!("Pressed e");
                     let selection = sel.selection();
                     selection.map(|selection| {
@@ -102,31 +84,249 @@ pub mod update {
                             run::edit_file(s, &selection);
                         })
                     })
-                });
+                })
+                .on_event('L', run::toggle_long_listing)
+                .on_event('.', run::toggle_hidden_files)
+                .on_event('s', run::cycle_sort_mode)
+                .on_event('r', run::toggle_reverse_sort)
+                .on_event('D', run::toggle_directories_first);
+
+            let preview = TextView::empty()
+                .with_name("file_preview")
+                .scrollable()
+                .with_name("preview_scroll");
+            layout.add_child(
+                LinearLayout::horizontal()
+                    .child(ResizedView::with_full_screen(
+                        on_event.scrollable().with_name("filelist_view"),
+                    ))
+                    .child(ResizedView::with_full_screen(
+                        Panel::new(preview).with_name("preview_panel"),
+                    )),
+            );
+        });
+    }
+
+    /// Shows the preview of the file beside the file list, from its start
+    /// unless `scroll_to_top` is false.
+    pub fn file_preview(s: &mut Cursive, filename: &str, content: String, scroll_to_top: bool) {
+        s.call_on_name("preview_panel", |panel: &mut PreviewPanel| {
+            panel.set_title(filename);
+        });
+        s.call_on_name("file_preview", |view: &mut TextView| {
+            view.set_content(content);
+        });
+        if scroll_to_top {
+            s.call_on_name("preview_scroll", |view: &mut PreviewScroll| {
+                view.scroll_to_top();
+            });
+        }
+    }
 
+    /// Path of the file selected in the file list.
+    pub fn file_list_selection(s: &mut Cursive) -> Option<String> {
+        s.call_on_name("filelist_view", |view: &mut FileListView| {
+            view.get_inner()
+                .get_inner()
+                .selection()
+                .map(|selection| selection.to_string())
+        })
+        .flatten()
+    }
+
+    /// Updates the file list with the new listing, only changing the items
+    /// which changed to keep the selection and the scroll position.
+    pub fn refresh_file_list(s: &mut Cursive, listings: Vec<Listing>, long: bool) {
+        let items = file_list_items(listings, long);
+        s.call_on_name("filelist_view", |view: &mut FileListView| {
+            let select = view.get_inner_mut().get_inner_mut();
+            let selection = select.selection().map(|selection| selection.to_string());
+            for index in (0..select.len()).rev() {
+                if let Some((_, value)) = select.get_item(index) {
+                    if !items.iter().any(|(_, path)| path == value) {
+                        select.remove_item(index);
+                    }
+                }
+            }
+            for (index, (label, path)) in items.into_iter().enumerate() {
+                let position = select
+                    .iter()
+                    .skip(index)
+                    .position(|(_, value)| *value == path)
+                    .map(|position| position + index);
+                match position {
+                    Some(position) if position == index => {
+                        if let Some((item_label, _)) = select.get_item_mut(index) {
+                            *item_label = label;
+                        }
+                    }
+                    // The sort order changed.
+                    Some(position) => {
+                        select.remove_item(position);
+                        select.insert_item(index, label, path);
+                    }
+                    None => select.insert_item(index, label, path),
+                }
+            }
+            let selected = selection
+                .and_then(|selection| select.iter().position(|(_, value)| *value == selection));
+            if let Some(selected) = selected {
+                select.set_selection(selected);
+            }
+        });
+    }
+
+    pub fn has_file_list(s: &mut Cursive) -> bool {
+        s.find_name::<FileListView>("filelist_view").is_some()
+    }
+
+    // Label and path of each listed file, with the directory headings.
+    fn file_list_items(listings: Vec<Listing>, long: bool) -> Vec<(StyledString, String)> {
+        let mut items = Vec::new();
+        for listing in listings {
+            // Selecting the heading opens its directory.
+            if let Some(heading) = &listing.heading {
+                items.push((
+                    StyledString::styled(format!("{}:", heading), Effect::Bold),
+                    heading.clone(),
+                ));
+            }
+            let columns = match long {
+                true => ls::long_columns(&listing.entries),
+                false => vec![String::new(); listing.entries.len()],
+            };
+            for (file_entry, columns) in listing.entries.iter().zip(columns) {
+                let color = match file_entry.filetype {
+                    FileType::Directory => Some(Color::Light(BaseColor::Blue)),
+                    FileType::Executable => Some(Color::Light(BaseColor::Green)),
+                    FileType::Symlink => Some(Color::Dark(BaseColor::Blue)),
+                    // Guessed from the name, reading each file being too slow.
+                    FileType::File => match filekind::from_name(Path::new(&file_entry.filename)) {
+                        Some(kind) => category_color(kind.category),
+                        None => None,
+                    },
+                    _ => None,
+                };
+                let mut label = StyledString::plain(columns);
+                match color {
+                    Some(color) => label.append_styled(file_entry.filename.clone(), color),
+                    None => label.append_plain(file_entry.filename.clone()),
+                }
+                items.push((label, listing.path(file_entry)));
+            }
+        }
+        items
+    }
+
+    fn category_color(category: Category) -> Option<Color> {
+        match category {
+            Category::Image | Category::Video => Some(Color::Light(BaseColor::Magenta)),
+            Category::Audio => Some(Color::Dark(BaseColor::Cyan)),
+            Category::Archive => Some(Color::Light(BaseColor::Red)),
+            _ => None,
+        }
+    }
+
+    pub fn command_output(s: &mut Cursive) {
+        detach_foreground(s);
+        s.call_on_name("command_layout", |layout: &mut LinearLayout| {
+            clear_output_layers(layout);
             layout.add_child(ResizedView::with_full_screen(
-                on_event.scrollable().with_name("filelist_view"),
+                ScrollView::new(TextView::empty().with_name("command_output"))
+                    .scroll_strategy(ScrollStrategy::StickToBottom),
             ));
+            layout.add_child(TextView::empty().with_name("command_error"));
         });
     }
 
-    pub fn command_output(s: &mut Cursive, result: CommandResult) {
+    pub fn append_output(s: &mut Cursive, output: &str) {
+        s.call_on_name("command_output", |view: &mut TextView| {
+            append_capped(view, output);
+        });
+    }
+
+    pub fn append_error(s: &mut Cursive, error: &str) {
+        s.call_on_name("command_error", |view: &mut TextView| {
+            append_capped(view, error);
+        });
+    }
+
+    // Appends the text, dropping the oldest one past the size kept for
+    // the output of jobs. A quarter more is dropped, for the view not to
+    // be rebuilt for each line.
+    fn append_capped(view: &mut TextView, text: &str) {
+        view.append(text);
+        let kept = {
+            let content = view.get_content();
+            let source = content.source();
+            match source.len() > job::MAX_OUTPUT_LEN {
+                true => {
+                    let cut = job::output_cut(source, job::MAX_OUTPUT_LEN * 3 / 4);
+                    Some(source[cut..].to_string())
+                }
+                false => None,
+            }
+        };
+        if let Some(kept) = kept {
+            view.set_content(kept);
+        }
+    }
+
+    pub fn command_terminal(s: &mut Cursive, terminal: TerminalView) {
+        detach_foreground(s);
         s.call_on_name("command_layout", |layout: &mut LinearLayout| {
             clear_output_layers(layout);
-            layout.add_child(ResizedView::with_full_screen(ScrollView::new(
-                TextView::new(result.output).with_name("command_output"),
-            )));
-            layout.add_child(TextView::new(result.error_output).with_name("command_error"));
+            layout.add_child(ResizedView::with_full_screen(
+                terminal.with_name("command_terminal"),
+            ));
+        });
+        if let Err(error) = s.focus_name("command_terminal") {
+            log::error!("Cannot focus terminal: {:?}", error);
+        }
+    }
+
+    pub fn file_viewer<V: View>(s: &mut Cursive, viewer: V) {
+        detach_foreground(s);
+        s.call_on_name("command_layout", |layout: &mut LinearLayout| {
+            clear_output_layers(layout);
+            layout.add_child(ResizedView::with_full_screen(
+                viewer.with_name("file_viewer"),
+            ));
+        });
+        if let Err(error) = s.focus_name("file_viewer") {
+            log::error!("Cannot focus file viewer: {:?}", error);
+        }
+    }
+
+    pub fn focus_command_line(s: &mut Cursive) {
+        if let Err(error) = s.focus_name("cli_input") {
+            log::error!("Cannot focus command line: {:?}", error);
+        }
+    }
+
+    pub fn command_result(s: &mut Cursive, result: &CommandResult) {
+        let color = match result.success() {
+            true => Color::Light(BaseColor::Green),
+            false => Color::Light(BaseColor::Red),
+        };
+        let status = StyledString::styled(result.to_string(), color);
+        s.call_on_name("command_layout", |layout: &mut LinearLayout| {
+            layout.add_child(TextView::new(status).with_name("command_status"));
         });
     }
 
     pub fn clear_command(s: &mut Cursive) {
+        set_command_line(s, "");
+    }
+
+    pub fn set_command_line(s: &mut Cursive, content: &str) {
         s.call_on_name("cli_input", |view: &mut CliView| {
-            view.set_content("");
+            view.set_content(content);
         });
     }
 
     pub fn show_error(s: &mut Cursive, error: String) {
+        detach_foreground(s);
         s.call_on_name("command_layout", |layout: &mut LinearLayout| {
             clear_output_layers(layout);
             layout.add_child(ResizedView::with_full_screen(
@@ -135,8 +335,33 @@ pub mod update {
         });
     }
 
+    pub fn notify(s: &mut Cursive, message: &str) {
+        s.call_on_name("notification", |view: &mut TextView| {
+            view.set_content(message);
+        });
+    }
+
+    pub fn title(s: &mut Cursive, title: &str) {
+        s.call_on_name("main_panel", |view: &mut Panel<LinearLayout>| {
+            view.set_title(title);
+        });
+    }
+
+    // The job whose output was displayed keeps running in the background
+    // when its output is replaced.
+    fn detach_foreground(s: &mut Cursive) {
+        s.with_user_data(|state: &mut RunState| state.jobs.detach_foreground());
+    }
+
     fn clear_output_layers(layout: &mut LinearLayout) {
-        let children_names = ["command_output", "command_error", "filelist_view"];
+        let children_names = [
+            "command_output",
+            "command_error",
+            "command_status",
+            "command_terminal",
+            "filelist_view",
+            "file_viewer",
+        ];
         for child_name in &children_names {
             match layout.find_child_from_name(child_name) {
                 Some(child_index) => {
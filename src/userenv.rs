@@ -36,6 +36,7 @@ knowledge of the CeCILL license and that you accept its terms.
 
 pub mod userenv {
     use std::env;
+    use std::path::PathBuf;
 
     pub fn editor() -> String {
         match env::var("EDITOR") {
@@ -47,6 +48,54 @@ pub mod userenv {
         }
     }
 
+    pub fn pager() -> String {
+        match env::var("PAGER") {
+            Ok(pager) => pager,
+            Err(_err) => String::from("less"),
+        }
+    }
+
+    // Colon separated list of the programs which need the full terminal.
+    pub fn fullscreen_programs() -> Option<String> {
+        env::var("MANETTE_FULLSCREEN").ok()
+    }
+
+    // Whether a graphical session is running, where desktop applications
+    // can be opened.
+    pub fn has_display() -> bool {
+        ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|name| matches!(env::var_os(name), Some(value) if !value.is_empty()))
+    }
+
+    pub fn home() -> String {
+        match env::var("HOME") {
+            Ok(home) => home,
+            Err(_err) => {
+                log::warn!("Cannot get HOME from environment");
+                String::from("/")
+            }
+        }
+    }
+
+    // Directory for user data files, following the XDG base directory
+    // specification.
+    pub fn data_dir() -> PathBuf {
+        match env::var("XDG_DATA_HOME") {
+            Ok(data_home) if !data_home.is_empty() => PathBuf::from(data_home),
+            _ => PathBuf::from(home()).join(".local").join("share"),
+        }
+    }
+
+    // Directory for user configuration files, following the XDG base
+    // directory specification.
+    pub fn config_dir() -> PathBuf {
+        match env::var("XDG_CONFIG_HOME") {
+            Ok(config_home) if !config_home.is_empty() => PathBuf::from(config_home),
+            _ => PathBuf::from(home()).join(".config"),
+        }
+    }
+
     pub fn path() -> String {
         match env::var("PATH") {
             Ok(path) => path,
@@ -0,0 +1,519 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod vi {
+    use crate::edit::edit;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Mode {
+        Insert,
+        Normal,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Motion {
+        Left,
+        Right,
+        WordForward,
+        WordBackward,
+        WordEnd,
+        StartOfLine,
+        EndOfLine,
+        // f, t, F and T: move to, or next to, the char.
+        Find {
+            ch: char,
+            till: bool,
+            backward: bool,
+        },
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Operator {
+        Delete,
+        Change,
+        Yank,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Action {
+        Move(Motion),
+        Operate(Operator, Motion),
+        // dd, cc and yy work on the whole line.
+        OperateLine(Operator),
+        Insert,
+        InsertAtStart,
+        Append,
+        AppendAtEnd,
+        Paste { before: bool },
+        HistoryPrevious,
+        HistoryNext,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Command {
+        pub count: usize,
+        pub action: Action,
+    }
+
+    // Highest count of a command, larger ones being reduced to it so
+    // repeating the command stays fast.
+    const MAX_COUNT: usize = 9999;
+
+    // Keys typed in normal mode, until they make a complete command.
+    #[derive(Default)]
+    pub struct Parser {
+        count: Option<usize>,
+        // Operator waiting for its motion, with the count typed before it.
+        operator: Option<(Operator, usize)>,
+        // f, t, F or T waiting for its char.
+        find: Option<(bool, bool)>,
+    }
+
+    impl Parser {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        pub fn reset(&mut self) {
+            *self = Parser::new();
+        }
+
+        /// Reads a key, returning the command once complete.
+        pub fn push(&mut self, ch: char) -> Option<Command> {
+            if let Some((till, backward)) = self.find.take() {
+                return self.motion(Motion::Find { ch, till, backward });
+            }
+            match ch {
+                '1'..='9' => self.digit(ch),
+                '0' if self.count.is_some() => self.digit(ch),
+                '0' => self.motion(Motion::StartOfLine),
+                '^' => self.motion(Motion::StartOfLine),
+                '$' => self.motion(Motion::EndOfLine),
+                'h' => self.motion(Motion::Left),
+                'l' | ' ' => self.motion(Motion::Right),
+                'w' | 'W' => self.motion(Motion::WordForward),
+                'b' | 'B' => self.motion(Motion::WordBackward),
+                'e' | 'E' => self.motion(Motion::WordEnd),
+                'f' => self.find(false, false),
+                't' => self.find(true, false),
+                'F' => self.find(false, true),
+                'T' => self.find(true, true),
+                'd' => self.operator(Operator::Delete),
+                'c' => self.operator(Operator::Change),
+                'y' => self.operator(Operator::Yank),
+                _ if self.operator.is_some() => {
+                    // Not a motion, cancel the operator.
+                    self.reset();
+                    None
+                }
+                'x' => self.action(Action::Operate(Operator::Delete, Motion::Right)),
+                'X' => self.action(Action::Operate(Operator::Delete, Motion::Left)),
+                's' => self.action(Action::Operate(Operator::Change, Motion::Right)),
+                'D' => self.action(Action::Operate(Operator::Delete, Motion::EndOfLine)),
+                'C' => self.action(Action::Operate(Operator::Change, Motion::EndOfLine)),
+                'S' => self.action(Action::OperateLine(Operator::Change)),
+                'Y' => self.action(Action::OperateLine(Operator::Yank)),
+                'i' => self.action(Action::Insert),
+                'a' => self.action(Action::Append),
+                'I' => self.action(Action::InsertAtStart),
+                'A' => self.action(Action::AppendAtEnd),
+                'p' => self.action(Action::Paste { before: false }),
+                'P' => self.action(Action::Paste { before: true }),
+                'k' => self.action(Action::HistoryPrevious),
+                'j' => self.action(Action::HistoryNext),
+                _ => {
+                    self.reset();
+                    None
+                }
+            }
+        }
+
+        fn digit(&mut self, ch: char) -> Option<Command> {
+            let digit = ch.to_digit(10).unwrap() as usize;
+            let count = self.count.unwrap_or(0).saturating_mul(10);
+            self.count = Some(count.saturating_add(digit).min(MAX_COUNT));
+            None
+        }
+
+        fn find(&mut self, till: bool, backward: bool) -> Option<Command> {
+            self.find = Some((till, backward));
+            None
+        }
+
+        fn operator(&mut self, operator: Operator) -> Option<Command> {
+            match self.operator {
+                // Doubled operator, like dd.
+                Some((pending, _)) if pending == operator => {
+                    self.action(Action::OperateLine(operator))
+                }
+                Some(_) => {
+                    self.reset();
+                    None
+                }
+                None => {
+                    self.operator = Some((operator, self.count.take().unwrap_or(1)));
+                    None
+                }
+            }
+        }
+
+        fn motion(&mut self, motion: Motion) -> Option<Command> {
+            match self.operator {
+                Some((operator, _)) => self.action(Action::Operate(operator, motion)),
+                None => self.action(Action::Move(motion)),
+            }
+        }
+
+        fn action(&mut self, action: Action) -> Option<Command> {
+            // Counts typed before the operator and the motion multiply.
+            let operator_count = self.operator.map(|(_, count)| count).unwrap_or(1);
+            let count = operator_count
+                .saturating_mul(self.count.unwrap_or(1))
+                .min(MAX_COUNT);
+            self.reset();
+            Some(Command { count, action })
+        }
+    }
+
+    #[derive(PartialEq)]
+    enum CharClass {
+        Blank,
+        Word,
+        Punctuation,
+    }
+
+    fn class(grapheme: &str) -> CharClass {
+        match grapheme.chars().next() {
+            Some(ch) if ch.is_whitespace() => CharClass::Blank,
+            Some(ch) if ch.is_alphanumeric() || ch == '_' => CharClass::Word,
+            _ => CharClass::Punctuation,
+        }
+    }
+
+    // Start of the next word, vi words being runs of word characters or
+    // runs of punctuation.
+    fn word_forward(content: &str, cursor: usize) -> usize {
+        let mut graphemes = content[cursor..].grapheme_indices(true).peekable();
+        if let Some((_, first)) = graphemes.next() {
+            let first_class = class(first);
+            while let Some((_, grapheme)) = graphemes.peek() {
+                if first_class == CharClass::Blank || class(grapheme) != first_class {
+                    break;
+                }
+                graphemes.next();
+            }
+        }
+        graphemes
+            .find(|(_, grapheme)| class(grapheme) != CharClass::Blank)
+            .map(|(index, _)| cursor + index)
+            .unwrap_or(content.len())
+    }
+
+    fn word_backward(content: &str, cursor: usize) -> usize {
+        let mut graphemes = content[..cursor].grapheme_indices(true).rev().peekable();
+        while let Some((_, grapheme)) = graphemes.peek() {
+            if class(grapheme) != CharClass::Blank {
+                break;
+            }
+            graphemes.next();
+        }
+        let (mut start, first) = match graphemes.next() {
+            Some(grapheme) => grapheme,
+            None => return 0,
+        };
+        let first_class = class(first);
+        for (index, grapheme) in graphemes {
+            if class(grapheme) != first_class {
+                break;
+            }
+            start = index;
+        }
+        start
+    }
+
+    // Last grapheme of the current or next word.
+    fn word_end(content: &str, cursor: usize) -> usize {
+        let start = edit::next_grapheme(content, cursor);
+        let mut graphemes = content[start..].grapheme_indices(true).peekable();
+        while let Some((_, grapheme)) = graphemes.peek() {
+            if class(grapheme) != CharClass::Blank {
+                break;
+            }
+            graphemes.next();
+        }
+        let (mut end, first) = match graphemes.next() {
+            Some(grapheme) => grapheme,
+            None => return last_grapheme(content),
+        };
+        let first_class = class(first);
+        for (index, grapheme) in graphemes {
+            if class(grapheme) != first_class {
+                break;
+            }
+            end = index;
+        }
+        start + end
+    }
+
+    // Position of the count-th occurrence of the char, or next to it
+    // with till.
+    fn find(
+        content: &str,
+        cursor: usize,
+        ch: char,
+        till: bool,
+        backward: bool,
+        count: usize,
+    ) -> Option<usize> {
+        let mut buffer = [0; 4];
+        let needle = &*ch.encode_utf8(&mut buffer);
+        if backward {
+            let (index, _) = content[..cursor]
+                .grapheme_indices(true)
+                .rev()
+                .filter(|(_, grapheme)| *grapheme == needle)
+                .nth(count - 1)?;
+            Some(match till {
+                true => edit::next_grapheme(content, index),
+                false => index,
+            })
+        } else {
+            let start = edit::next_grapheme(content, cursor);
+            let (index, _) = content[start..]
+                .grapheme_indices(true)
+                .filter(|(_, grapheme)| *grapheme == needle)
+                .nth(count - 1)?;
+            Some(match till {
+                true => edit::previous_grapheme(content, start + index),
+                false => start + index,
+            })
+        }
+    }
+
+    /// Start of the last grapheme, where the cursor stays in normal mode.
+    pub fn last_grapheme(content: &str) -> usize {
+        edit::previous_grapheme(content, content.len())
+    }
+
+    /// Position the motion moves the cursor to, None if it cannot move.
+    pub fn target(content: &str, cursor: usize, motion: Motion, count: usize) -> Option<usize> {
+        if let Motion::Find { ch, till, backward } = motion {
+            return find(content, cursor, ch, till, backward, count);
+        }
+        let mut position = cursor;
+        for _ in 0..count {
+            position = match motion {
+                Motion::Left => edit::previous_grapheme(content, position),
+                Motion::Right => edit::next_grapheme(content, position),
+                Motion::WordForward => word_forward(content, position),
+                Motion::WordBackward => word_backward(content, position),
+                Motion::WordEnd => word_end(content, position),
+                Motion::StartOfLine => 0,
+                Motion::EndOfLine => content.len(),
+                Motion::Find { .. } => position,
+            };
+        }
+        Some(position)
+    }
+
+    /// Byte range an operator applies to with the motion.
+    pub fn range(
+        content: &str,
+        cursor: usize,
+        operator: Operator,
+        motion: Motion,
+        count: usize,
+    ) -> Option<(usize, usize)> {
+        // Like vi, cw changes up to the end of the word.
+        let motion = match (operator, motion) {
+            (Operator::Change, Motion::WordForward) if !content[cursor..].starts_with(' ') => {
+                Motion::WordEnd
+            }
+            _ => motion,
+        };
+        let target = target(content, cursor, motion, count)?;
+        // Motions to a char include it.
+        let inclusive = matches!(
+            motion,
+            Motion::WordEnd
+                | Motion::Find {
+                    backward: false,
+                    ..
+                }
+        );
+        if target < cursor {
+            Some((target, cursor))
+        } else if inclusive {
+            Some((cursor, edit::next_grapheme(content, target)))
+        } else {
+            Some((cursor, target))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn parse(keys: &str) -> Option<Command> {
+            let mut parser = Parser::new();
+            let mut command = None;
+            for ch in keys.chars() {
+                command = parser.push(ch);
+            }
+            command
+        }
+
+        #[test]
+        fn test_parser() {
+            assert_eq!(
+                parse("3w"),
+                Some(Command {
+                    count: 3,
+                    action: Action::Move(Motion::WordForward)
+                })
+            );
+            assert_eq!(
+                parse("2d3w"),
+                Some(Command {
+                    count: 6,
+                    action: Action::Operate(Operator::Delete, Motion::WordForward)
+                })
+            );
+            assert_eq!(
+                parse("ctx"),
+                Some(Command {
+                    count: 1,
+                    action: Action::Operate(
+                        Operator::Change,
+                        Motion::Find {
+                            ch: 'x',
+                            till: true,
+                            backward: false
+                        }
+                    )
+                })
+            );
+            assert_eq!(
+                parse("10l"),
+                Some(Command {
+                    count: 10,
+                    action: Action::Move(Motion::Right)
+                })
+            );
+            assert_eq!(
+                parse("yy"),
+                Some(Command {
+                    count: 1,
+                    action: Action::OperateLine(Operator::Yank)
+                })
+            );
+            assert_eq!(
+                parse("2d99999999999999999999w"),
+                Some(Command {
+                    count: MAX_COUNT,
+                    action: Action::Operate(Operator::Delete, Motion::WordForward)
+                })
+            );
+            assert_eq!(parse("99999999999999999999p").unwrap().count, MAX_COUNT);
+            assert_eq!(parse("d"), None);
+            assert_eq!(parse("dz"), None);
+        }
+
+        #[test]
+        fn test_word_motions() {
+            let content = "git commit -m 'fix' ";
+            let word_forward = |cursor| target(content, cursor, Motion::WordForward, 1);
+            assert_eq!(word_forward(0), Some(4));
+            assert_eq!(word_forward(4), Some(11));
+            assert_eq!(word_forward(11), Some(12));
+            assert_eq!(word_forward(14), Some(15));
+            assert_eq!(word_forward(19), Some(20));
+            assert_eq!(target(content, 19, Motion::WordBackward, 2), Some(15));
+            assert_eq!(target(content, 4, Motion::WordBackward, 1), Some(0));
+            assert_eq!(target(content, 0, Motion::WordEnd, 1), Some(2));
+            assert_eq!(target(content, 2, Motion::WordEnd, 1), Some(9));
+            assert_eq!(target("日本 語", 0, Motion::WordForward, 1), Some(7));
+        }
+
+        #[test]
+        fn test_find() {
+            let content = "a,b,c";
+            let motion = |ch, till, backward| Motion::Find { ch, till, backward };
+            assert_eq!(target(content, 0, motion(',', false, false), 2), Some(3));
+            assert_eq!(target(content, 0, motion('c', true, false), 1), Some(3));
+            assert_eq!(target(content, 4, motion(',', false, true), 1), Some(3));
+            assert_eq!(target(content, 4, motion('a', true, true), 1), Some(1));
+            assert_eq!(target(content, 0, motion('z', false, false), 1), None);
+            assert_eq!(target(content, 0, motion(',', true, false), 2), Some(2));
+        }
+
+        #[test]
+        fn test_range() {
+            let content = "echo hello world";
+            assert_eq!(
+                range(content, 5, Operator::Delete, Motion::WordForward, 1),
+                Some((5, 11))
+            );
+            assert_eq!(
+                range(content, 5, Operator::Change, Motion::WordForward, 1),
+                Some((5, 10))
+            );
+            assert_eq!(
+                range(content, 5, Operator::Delete, Motion::EndOfLine, 1),
+                Some((5, 16))
+            );
+            assert_eq!(
+                range(content, 5, Operator::Delete, Motion::WordBackward, 1),
+                Some((0, 5))
+            );
+            assert_eq!(
+                range(
+                    content,
+                    0,
+                    Operator::Delete,
+                    Motion::Find {
+                        ch: 'o',
+                        till: false,
+                        backward: false
+                    },
+                    1
+                ),
+                Some((0, 4))
+            );
+        }
+    }
+}
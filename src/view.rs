@@ -36,24 +36,61 @@ knowledge of the CeCILL license and that you accept its terms.
 
 use crate::autocomplete::{autocomplete, CompletionChoice};
 use crate::autocompleteview::AutocompletePopup;
+use crate::command::run;
+use crate::edit::edit::{self, KillRing, KILL_RING_SIZE};
+use crate::highlight::highlight::{self, Kind, Span};
+use crate::history::history::{History, HISTORY_SIZE};
+use crate::historysearchview::HistorySearchPopup;
+use crate::lexer::lexer;
+use crate::vi::vi::{self, Action, Mode, Operator};
 use cursive::direction::Direction;
 use cursive::event::{Callback, Event, EventResult, Key};
-use cursive::theme::{ColorStyle, Effect};
+use cursive::theme::{BaseColor, Color, ColorStyle, Effect, PaletteColor, Style};
 use cursive::view::Position;
-use cursive::{Cursive, Printer, View, With, XY};
+use cursive::{Cursive, Printer, Vec2, View, With, XY};
 use std::rc::Rc;
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
+type OnSubmit = dyn Fn(&mut Cursive, &str);
+
 pub struct CliView {
     // Current content
     content: Rc<String>,
     // Cursor position in the content, in bytes.
     cursor: usize,
     // Callback when <Enter> is pressed
-    on_submit: Option<Rc<dyn Fn(&mut Cursive, &str)>>,
+    on_submit: Option<Rc<OnSubmit>>,
     // Character to fill empty space.
     filler: String,
+    // Previously submitted commands
+    history: History,
+    // Texts killed, to be yanked back.
+    kill_ring: KillRing,
+    // Last editing action, to merge consecutive kills and cycle yanks.
+    last_edit: LastEdit,
+    // Escape was pressed, the next char is read with Alt as terminals
+    // send Alt-x as Escape then x.
+    escape: bool,
+    // Ctrl-X was pressed, starting a two keys binding.
+    ctrl_x: bool,
+    // Editing mode when vi key bindings are enabled.
+    mode: Option<Mode>,
+    // Keys typed in vi normal mode.
+    vi_parser: vi::Parser,
+    // Highlighted parts of the content and suggested end of the command,
+    // computed when the content changes.
+    highlights: Vec<Span>,
+    suggestion: Option<String>,
+    highlighted_content: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LastEdit {
+    Other,
+    Kill,
+    // Byte range of the yanked text.
+    Yank(usize, usize),
 }
 
 impl CliView {
@@ -63,6 +100,16 @@ impl CliView {
             cursor: 0,
             on_submit: None,
             filler: " ".to_string(),
+            history: History::new(HISTORY_SIZE),
+            kill_ring: KillRing::new(KILL_RING_SIZE),
+            last_edit: LastEdit::Other,
+            escape: false,
+            ctrl_x: false,
+            mode: None,
+            vi_parser: vi::Parser::new(),
+            highlights: Vec::new(),
+            suggestion: None,
+            highlighted_content: String::new(),
         }
     }
 
@@ -82,13 +129,70 @@ impl CliView {
     pub fn backspace(&mut self) -> EventResult {
         let len = self.content[..self.cursor]
             .graphemes(true)
-            .last()
+            .next_back()
             .unwrap()
             .len();
         self.cursor -= len;
         EventResult::Consumed(Some(self.remove(len)))
     }
 
+    fn delete(&mut self) -> EventResult {
+        let len = edit::next_grapheme(&self.content, self.cursor) - self.cursor;
+        EventResult::Consumed(Some(self.remove(len)))
+    }
+
+    fn move_cursor(&mut self, cursor: usize) -> EventResult {
+        self.set_cursor(cursor);
+        EventResult::Consumed(None)
+    }
+
+    // Removes the text between start and end, saving it in the kill ring.
+    // Consecutive kills are saved as a single entry.
+    fn kill(&mut self, start: usize, end: usize, previous_edit: LastEdit) -> EventResult {
+        if start == end {
+            return EventResult::Consumed(None);
+        }
+        let text: String = Rc::make_mut(&mut self.content).drain(start..end).collect();
+        if previous_edit != LastEdit::Kill {
+            self.kill_ring.push(&text);
+        } else if end <= self.cursor {
+            self.kill_ring.prepend(&text);
+        } else {
+            self.kill_ring.append(&text);
+        }
+        self.cursor = start;
+        self.last_edit = LastEdit::Kill;
+        EventResult::Consumed(None)
+    }
+
+    fn yank(&mut self) -> EventResult {
+        if let Some(text) = self.kill_ring.yank() {
+            let text = text.to_string();
+            self.insert_yanked(&text);
+        }
+        EventResult::Consumed(None)
+    }
+
+    // Replaces the text just yanked by the previous one in the kill ring.
+    fn yank_pop(&mut self, previous_edit: LastEdit) -> EventResult {
+        if let LastEdit::Yank(start, end) = previous_edit {
+            if let Some(text) = self.kill_ring.yank_pop() {
+                let text = text.to_string();
+                Rc::make_mut(&mut self.content).replace_range(start..end, "");
+                self.cursor = start;
+                self.insert_yanked(&text);
+            }
+        }
+        EventResult::Consumed(None)
+    }
+
+    fn insert_yanked(&mut self, text: &str) {
+        let start = self.cursor;
+        Rc::make_mut(&mut self.content).insert_str(start, text);
+        self.cursor += text.len();
+        self.last_edit = LastEdit::Yank(start, self.cursor);
+    }
+
     // Allows setting the on_submit callback on an existing
     // view
     pub fn set_on_submit<F>(&mut self, callback: F)
@@ -107,6 +211,50 @@ impl CliView {
         self.with(|v| v.set_on_submit(callback))
     }
 
+    // Allows setting the history on an existing view
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
+
+    // Allows setting the history when creating the view
+    pub fn history(self, history: History) -> Self {
+        self.with(|v| v.set_history(history))
+    }
+
+    // Allows enabling vi key bindings on an existing view
+    pub fn set_vi_mode(&mut self, enabled: bool) {
+        self.mode = match enabled {
+            true => Some(Mode::Insert),
+            false => None,
+        };
+        self.vi_parser.reset();
+    }
+
+    // Allows enabling vi key bindings when creating the view
+    pub fn vi_mode(self, enabled: bool) -> Self {
+        self.with(|v| v.set_vi_mode(enabled))
+    }
+
+    fn history_previous(&mut self) -> EventResult {
+        if let Some(entry) = self.history.previous(&self.content) {
+            let entry = entry.to_string();
+            self.set_content(entry);
+        }
+        EventResult::Consumed(None)
+    }
+
+    // Past the last entry, Down moves the focus to the output below.
+    fn history_next(&mut self) -> EventResult {
+        match self.history.next() {
+            Some(entry) => {
+                let entry = entry.to_string();
+                self.set_content(entry);
+                EventResult::Consumed(None)
+            }
+            None => EventResult::Ignored,
+        }
+    }
+
     pub fn set_content<S: Into<String>>(&mut self, content: S) {
         let content = content.into();
         let len = content.len();
@@ -133,6 +281,202 @@ impl CliView {
         })
     }
 
+    fn history_search(&mut self) -> EventResult {
+        log::debug!("Creating history search popup");
+        let offset = XY::new(3, 4);
+        let entries = Rc::new(self.history.entries().to_vec());
+        EventResult::with_cb(move |s| {
+            s.screen_mut().add_layer_at(
+                Position::absolute(offset),
+                HistorySearchPopup::new(entries.clone()),
+            )
+        })
+    }
+
+    // Most recent command from the history starting with the content, or
+    // its only completion.
+    fn find_suggestion(&self) -> Option<String> {
+        if self.content.trim().is_empty() {
+            return None;
+        }
+        if let Some(entry) = self.history.suggest(&self.content) {
+            return Some(entry[self.content.len()..].to_string());
+        }
+        match autocomplete::autocomplete(&self.content) {
+            Ok(choices) if choices.len() == 1 => choices[0]
+                .completion
+                .strip_prefix(self.content.as_str())
+                .filter(|suggestion| !suggestion.is_empty())
+                .map(String::from),
+            Ok(_) => None,
+            Err(error) => {
+                log::debug!("Cannot autocomplete: {:?}", error);
+                None
+            }
+        }
+    }
+
+    // Suggestion shown after the cursor, if still matching the content.
+    fn current_suggestion(&self) -> Option<&str> {
+        if self.cursor != self.content.len() || *self.content != self.highlighted_content {
+            return None;
+        }
+        self.suggestion.as_deref()
+    }
+
+    fn accept_suggestion(&mut self) -> bool {
+        match self.current_suggestion() {
+            Some(suggestion) => {
+                let content = format!("{}{}", self.content, suggestion);
+                self.set_content(content);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Style of the char at the index, from the highlighted parts it is in.
+    fn style_at(&self, index: usize) -> Style {
+        let mut kind = None;
+        let mut underline = false;
+        for span in &self.highlights {
+            if (span.start..span.end).contains(&index) {
+                match span.kind {
+                    Kind::Path => underline = true,
+                    span_kind => kind = Some(span_kind),
+                }
+            }
+        }
+        let mut style = match kind.map(highlight_color) {
+            Some(color) => Style::from(ColorStyle::new(color, PaletteColor::Primary)),
+            None => Style::from(ColorStyle::primary()).combine(Effect::Reverse),
+        };
+        if underline {
+            style.effects.insert(Effect::Underline);
+        }
+        style
+    }
+
+    fn enter_normal_mode(&mut self) -> EventResult {
+        // Like vi, leaving insert mode moves the cursor back on the last
+        // char typed.
+        self.cursor = edit::previous_grapheme(&self.content, self.cursor);
+        self.mode = Some(Mode::Normal);
+        self.vi_parser.reset();
+        EventResult::Consumed(None)
+    }
+
+    // In normal mode the cursor stays on a char.
+    fn clamp_cursor(&mut self) {
+        if self.mode == Some(Mode::Normal) && self.cursor >= self.content.len() {
+            self.cursor = vi::last_grapheme(&self.content);
+        }
+    }
+
+    fn normal_mode_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Char(ch) => {
+                if let Some(command) = self.vi_parser.push(ch) {
+                    self.run_vi_command(command);
+                }
+                EventResult::Consumed(None)
+            }
+            Event::Key(Key::Esc) => {
+                self.vi_parser.reset();
+                EventResult::Consumed(None)
+            }
+            Event::Key(Key::Backspace) => {
+                self.move_cursor(edit::previous_grapheme(&self.content, self.cursor))
+            }
+            Event::Key(Key::Enter) => {
+                self.set_vi_mode(true);
+                self.edit_event(event)
+            }
+            _ => {
+                let result = self.edit_event(event);
+                self.clamp_cursor();
+                result
+            }
+        }
+    }
+
+    fn run_vi_command(&mut self, command: vi::Command) {
+        let count = command.count;
+        match command.action {
+            Action::Move(motion) => {
+                if let Some(target) = vi::target(&self.content, self.cursor, motion, count) {
+                    self.cursor = target;
+                }
+            }
+            Action::Operate(operator, motion) => {
+                let range = vi::range(&self.content, self.cursor, operator, motion, count);
+                if let Some((start, end)) = range {
+                    self.operate(operator, start, end);
+                }
+            }
+            Action::OperateLine(operator) => self.operate(operator, 0, self.content.len()),
+            Action::Insert => self.mode = Some(Mode::Insert),
+            Action::InsertAtStart => {
+                self.cursor = 0;
+                self.mode = Some(Mode::Insert);
+            }
+            Action::Append => {
+                self.cursor = edit::next_grapheme(&self.content, self.cursor);
+                self.mode = Some(Mode::Insert);
+            }
+            Action::AppendAtEnd => {
+                self.cursor = self.content.len();
+                self.mode = Some(Mode::Insert);
+            }
+            Action::Paste { before } => self.paste(before, count),
+            Action::HistoryPrevious => {
+                for _ in 0..count {
+                    self.history_previous();
+                }
+            }
+            Action::HistoryNext => {
+                for _ in 0..count {
+                    self.history_next();
+                }
+            }
+        }
+        self.clamp_cursor();
+    }
+
+    // Applies a vi operator to the text between start and end, which is
+    // saved in the kill ring.
+    fn operate(&mut self, operator: Operator, start: usize, end: usize) {
+        if start < end {
+            self.kill_ring.push(&self.content[start..end]);
+        }
+        if operator != Operator::Yank {
+            Rc::make_mut(&mut self.content).replace_range(start..end, "");
+        }
+        self.cursor = start;
+        if operator == Operator::Change {
+            self.mode = Some(Mode::Insert);
+        }
+    }
+
+    fn paste(&mut self, before: bool, count: usize) {
+        let text = match self.kill_ring.yank() {
+            Some(text) => text.repeat(count),
+            None => return,
+        };
+        if !before {
+            self.cursor = edit::next_grapheme(&self.content, self.cursor);
+        }
+        self.insert_yanked(&text);
+        // Stay on the last char pasted.
+        self.cursor = edit::previous_grapheme(&self.content, self.cursor);
+    }
+
+    // Opens the command line in the editor, loading it back once saved.
+    fn edit_in_editor(&mut self) -> EventResult {
+        let content = self.content.to_string();
+        EventResult::with_cb(move |s| run::edit_command_line(s, &content))
+    }
+
     fn autocomplete(&mut self) -> EventResult {
         log::debug!("Trigger autocompletion");
         let completion = autocomplete::autocomplete(&self.content);
@@ -151,51 +495,69 @@ impl CliView {
             }
         }
     }
-}
-
-impl View for CliView {
-    fn draw(&self, printer: &Printer) {
-        let width = self.content.width();
-        printer.with_color(ColorStyle::primary(), |printer| {
-            printer.with_effect(Effect::Reverse, |printer| {
-                printer.print((0, 0), &self.content);
-                let filler_len = (printer.size.x - width) / self.filler.width();
-                printer.print_hline((width, 0), filler_len, self.filler.as_str());
-            });
-        });
-        // Now print cursor
-        printer.with_color(ColorStyle::highlight(), |printer| {
-            if printer.focused {
-                let c: &str = if self.cursor == self.content.len() {
-                    &self.filler
-                } else {
-                    self.content[self.cursor..]
-                        .graphemes(true)
-                        .next()
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "Found no char at cursor {} in {}",
-                                self.cursor, &self.content
-                            )
-                        })
-                };
-                let offset = self.content[0..self.cursor].width();
-                printer.print((offset, 0), c);
-            }
-        });
-    }
-
-    fn take_focus(&mut self, _source: Direction) -> bool {
-        log::debug!("Should focus?");
-        true
-    }
 
-    fn on_event(&mut self, event: Event) -> EventResult {
+    fn edit_event(&mut self, event: Event) -> EventResult {
+        let previous_edit = std::mem::replace(&mut self.last_edit, LastEdit::Other);
+        let event = match (std::mem::replace(&mut self.escape, false), event) {
+            (true, Event::Char(ch)) => Event::AltChar(ch),
+            (true, Event::Key(key)) => Event::Alt(key),
+            (_, event) => event,
+        };
+        if std::mem::replace(&mut self.ctrl_x, false) && event == Event::CtrlChar('e') {
+            return self.edit_in_editor();
+        }
         match event {
+            Event::Key(Key::Esc) => {
+                self.escape = true;
+                self.last_edit = previous_edit;
+                EventResult::Consumed(None)
+            }
+            Event::CtrlChar('x') => {
+                self.ctrl_x = true;
+                self.last_edit = previous_edit;
+                EventResult::Consumed(None)
+            }
             Event::Char(ch) => EventResult::Consumed(Some(self.insert(ch))),
             Event::Key(Key::Backspace) if self.cursor > 0 => self.backspace(),
             Event::CtrlChar('h') if self.cursor > 0 => self.backspace(),
+            Event::Key(Key::Del) => self.delete(),
+            Event::Key(Key::Left) | Event::CtrlChar('b') => {
+                self.move_cursor(edit::previous_grapheme(&self.content, self.cursor))
+            }
+            Event::Key(Key::Right) | Event::CtrlChar('f') => match self.accept_suggestion() {
+                true => EventResult::Consumed(None),
+                false => self.move_cursor(edit::next_grapheme(&self.content, self.cursor)),
+            },
+            Event::Key(Key::Home) | Event::CtrlChar('a') => self.move_cursor(0),
+            Event::Key(Key::End) | Event::CtrlChar('e') => match self.accept_suggestion() {
+                true => EventResult::Consumed(None),
+                false => self.move_cursor(self.content.len()),
+            },
+            Event::AltChar('b') => {
+                self.move_cursor(edit::previous_word_start(&self.content, self.cursor))
+            }
+            Event::AltChar('f') => {
+                self.move_cursor(edit::next_word_end(&self.content, self.cursor))
+            }
+            Event::CtrlChar('w') => {
+                let start = edit::previous_blank_word_start(&self.content, self.cursor);
+                self.kill(start, self.cursor, previous_edit)
+            }
+            Event::AltChar('d') => {
+                let end = edit::next_word_end(&self.content, self.cursor);
+                self.kill(self.cursor, end, previous_edit)
+            }
+            Event::CtrlChar('u') => self.kill(0, self.cursor, previous_edit),
+            Event::CtrlChar('k') => self.kill(self.cursor, self.content.len(), previous_edit),
+            Event::CtrlChar('y') => self.yank(),
+            Event::AltChar('y') => self.yank_pop(previous_edit),
+            // Unfinished commands continue on a new line.
+            Event::Alt(Key::Enter) => EventResult::Consumed(Some(self.insert('\n'))),
+            Event::Key(Key::Enter) if lexer::is_incomplete(&self.content) => {
+                EventResult::Consumed(Some(self.insert('\n')))
+            }
             Event::Key(Key::Enter) => {
+                self.history.add(&self.content);
                 let cb = self.on_submit.clone().unwrap();
                 let content = Rc::clone(&self.content);
                 EventResult::with_cb(move |s| {
@@ -203,6 +565,19 @@ impl View for CliView {
                 })
             }
             Event::Key(Key::Tab) => self.autocomplete(),
+            Event::Key(Key::Up) => match edit::line_up(&self.content, self.cursor) {
+                Some(cursor) => self.move_cursor(cursor),
+                None => self.history_previous(),
+            },
+            Event::Key(Key::Down) => match edit::line_down(&self.content, self.cursor) {
+                Some(cursor) => self.move_cursor(cursor),
+                None => self.history_next(),
+            },
+            Event::CtrlChar('r') => self.history_search(),
+            Event::CtrlChar('c') => {
+                self.set_content("");
+                EventResult::with_cb(run::cancel_command)
+            }
             _ => {
                 log::debug!("Got unknown event {:?}", event);
                 EventResult::Ignored
@@ -210,3 +585,124 @@ impl View for CliView {
         }
     }
 }
+
+fn highlight_color(kind: Kind) -> Color {
+    match kind {
+        Kind::Command => Color::Light(BaseColor::Green),
+        Kind::UnknownCommand => Color::Light(BaseColor::Red),
+        Kind::String => Color::Light(BaseColor::Yellow),
+        Kind::Variable => Color::Light(BaseColor::Cyan),
+        Kind::Path => Color::TerminalDefault,
+    }
+}
+
+impl View for CliView {
+    fn draw(&self, printer: &Printer) {
+        let printer = &match self.mode {
+            Some(mode) => {
+                let indicator = match mode {
+                    Mode::Insert => "(ins) ",
+                    Mode::Normal => "(cmd) ",
+                };
+                printer.with_color(ColorStyle::secondary(), |printer| {
+                    printer.print((0, 0), indicator);
+                });
+                printer.offset((indicator.width(), 0))
+            }
+            None => printer.clone(),
+        };
+        let mut line_start = 0;
+        for (row, line) in self.content.split('\n').enumerate() {
+            let line_end = line_start + line.len();
+            let has_cursor = (line_start..=line_end).contains(&self.cursor);
+            // Scroll the line horizontally to keep the cursor visible.
+            let start = match has_cursor {
+                true => {
+                    line_start + edit::visible_start(line, self.cursor - line_start, printer.size.x)
+                }
+                false => line_start,
+            };
+            let visible = &self.content[start..line_end];
+            let width = visible.width();
+            let mut x = 0;
+            for (index, grapheme) in visible.grapheme_indices(true) {
+                printer.with_style(self.style_at(start + index), |printer| {
+                    printer.print((x, row), grapheme);
+                });
+                x += grapheme.width();
+            }
+            printer.with_color(ColorStyle::primary(), |printer| {
+                printer.with_effect(Effect::Reverse, |printer| {
+                    let filler_len = printer.size.x.saturating_sub(width) / self.filler.width();
+                    printer.print_hline((width, row), filler_len, self.filler.as_str());
+                });
+            });
+            let suggestion = match has_cursor {
+                true => self.current_suggestion(),
+                false => None,
+            };
+            if let Some(suggestion) = suggestion {
+                let first_line = suggestion.split('\n').next().unwrap_or_default();
+                let grey = ColorStyle::new(Color::Light(BaseColor::Black), PaletteColor::Primary);
+                printer.with_color(grey, |printer| {
+                    printer.print((width, row), first_line);
+                });
+            }
+            // Now print cursor
+            if has_cursor && printer.focused {
+                printer.with_color(ColorStyle::highlight(), |printer| {
+                    let c: &str = if let Some(suggestion) = suggestion {
+                        suggestion
+                            .graphemes(true)
+                            .next()
+                            .filter(|grapheme| *grapheme != "\n")
+                            .unwrap_or(&self.filler)
+                    } else if self.cursor == line_end {
+                        &self.filler
+                    } else {
+                        self.content[self.cursor..]
+                            .graphemes(true)
+                            .next()
+                            .unwrap_or_else(|| {
+                                panic!(
+                                    "Found no char at cursor {} in {}",
+                                    self.cursor, &self.content
+                                )
+                            })
+                    };
+                    let offset = self.content[start..self.cursor].width();
+                    printer.print((offset, row), c);
+                });
+            }
+            line_start = line_end + 1;
+        }
+    }
+
+    fn layout(&mut self, _size: Vec2) {
+        if *self.content != self.highlighted_content {
+            self.highlights =
+                highlight::highlight(&self.content, highlight::is_command, highlight::is_path);
+            self.highlighted_content = self.content.to_string();
+            self.suggestion = self.find_suggestion();
+        }
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        // One row per line of the command.
+        let rows = self.content.split('\n').count();
+        Vec2::new(constraint.x, rows.min(constraint.y))
+    }
+
+    fn take_focus(&mut self, _source: Direction) -> bool {
+        log::debug!("Should focus?");
+        true
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match self.mode {
+            Some(Mode::Normal) => self.normal_mode_event(event),
+            Some(Mode::Insert) if event == Event::Key(Key::Esc) => self.enter_normal_mode(),
+            _ => self.edit_event(event),
+        }
+    }
+}
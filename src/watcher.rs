@@ -0,0 +1,116 @@
+/*
+
+Copyright or © or Copr. Paul Ezvan (2022)
+
+paul@ezvan.fr
+
+This software is a computer program whose purpose is to provide a terminal file explorer.
+
+This software is governed by the CeCILL license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL license and that you accept its terms.
+
+*/
+
+pub mod watcher {
+    use cursive::{CbSink, Cursive};
+    use notify::event::{AccessKind, AccessMode, EventKind};
+    use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    // Changes happening together, as during a build, are handled once
+    // this delay after the first one.
+    const DEBOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+    /// Watches the listed directories, calling back the UI when their
+    /// files change.
+    pub struct DirectoryWatcher {
+        watcher: RecommendedWatcher,
+        watched: Vec<PathBuf>,
+    }
+
+    impl DirectoryWatcher {
+        pub fn new(sink: CbSink, on_change: fn(&mut Cursive)) -> notify::Result<Self> {
+            let pending = Arc::new(AtomicBool::new(false));
+            let watcher =
+                notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+                    Ok(event) if is_change(&event.kind) => {
+                        log::debug!("Watched files changed: {:?}", event.paths);
+                        if pending.swap(true, Ordering::SeqCst) {
+                            return;
+                        }
+                        let pending = pending.clone();
+                        let sink = sink.clone();
+                        thread::spawn(move || {
+                            thread::sleep(DEBOUNCE_DELAY);
+                            pending.store(false, Ordering::SeqCst);
+                            if let Err(error) = sink.send(Box::new(on_change)) {
+                                log::error!("Cannot refresh the listing: {:?}", error);
+                            }
+                        });
+                    }
+                    Ok(_) => (),
+                    Err(error) => log::error!("Cannot watch files: {:?}", error),
+                })?;
+            Ok(DirectoryWatcher {
+                watcher,
+                watched: Vec::new(),
+            })
+        }
+
+        /// Watches these directories instead of the previous ones.
+        pub fn watch(&mut self, dirs: Vec<PathBuf>) {
+            for dir in &self.watched {
+                if !dirs.contains(dir) {
+                    if let Err(error) = self.watcher.unwatch(dir) {
+                        log::debug!("Cannot stop watching {:?}: {:?}", dir, error);
+                    }
+                }
+            }
+            for dir in &dirs {
+                if !self.watched.contains(dir) {
+                    if let Err(error) = self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                        log::error!("Cannot watch {:?}: {:?}", dir, error);
+                    }
+                }
+            }
+            self.watched = dirs;
+        }
+    }
+
+    // Tells whether the event changes the listing, reading files does not.
+    fn is_change(kind: &EventKind) -> bool {
+        !matches!(
+            kind,
+            EventKind::Access(AccessKind::Read)
+                | EventKind::Access(AccessKind::Open(_))
+                | EventKind::Access(AccessKind::Close(AccessMode::Read))
+        )
+    }
+}